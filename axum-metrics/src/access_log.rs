@@ -0,0 +1,590 @@
+use std::{
+    fmt::{self, Write as _},
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    net::SocketAddr,
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, SyncSender},
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    extract::ConnectInfo,
+    http::{
+        header::{REFERER, USER_AGENT},
+        Request,
+    },
+};
+
+use crate::{
+    metadata::{RequestMetadata, ResponseMetadata},
+    Outcome,
+};
+
+/// Line format of an [`AccessLog`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessLogFormat {
+    /// Apache Common Log Format.
+    Common,
+    /// Apache Combined Log Format, adding the `Referer` and `User-Agent` to the common format.
+    Combined,
+    /// One JSON object per line, with the route, duration and outcome of the request.
+    Json,
+}
+
+/// Lines waiting to be written before new ones are dropped.
+const QUEUE_CAPACITY: usize = 8192;
+
+/// Writes a line for every request handled by a [`MetricLayer`](crate::MetricLayer).
+///
+/// The remote address is read from the [`ConnectInfo<SocketAddr>`] extension, so the app has to
+/// be served with `into_make_service_with_connect_info`. The response size is only known when
+/// the response has a `Content-Length` or an exact size hint.
+///
+/// Lines are handed to a dedicated thread doing the buffered writes, so requests never wait on
+/// the sink. Lines are dropped while the thread is more than 8192 lines behind. Dropping the
+/// access log waits for the queued lines to be written.
+pub struct AccessLog {
+    format: AccessLogFormat,
+    lines: Option<SyncSender<String>>,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl AccessLog {
+    pub fn new(format: AccessLogFormat, writer: impl Write + Send + 'static) -> Self {
+        let (lines, receiver) = mpsc::sync_channel(QUEUE_CAPACITY);
+        let writer = thread::Builder::new()
+            .name("access-log".to_string())
+            .spawn(move || write_lines(receiver, writer))
+            .expect("failed to spawn the access log thread");
+
+        Self {
+            format,
+            lines: Some(lines),
+            writer: Some(writer),
+        }
+    }
+
+    pub fn stdout(format: AccessLogFormat) -> Self {
+        Self::new(format, io::stdout())
+    }
+
+    pub(crate) fn log(
+        &self,
+        request: &RequestMetadata,
+        response: Option<&ResponseMetadata>,
+        outcome: Outcome,
+        duration: Duration,
+    ) {
+        if let Some(entry) = request.access() {
+            self.log_entry(entry, request.path(), response, outcome, duration);
+        }
+    }
+
+    fn log_entry(
+        &self,
+        entry: &AccessLogEntry,
+        route: &str,
+        response: Option<&ResponseMetadata>,
+        outcome: Outcome,
+        duration: Duration,
+    ) {
+        let mut line = String::new();
+        let _ = match self.format {
+            AccessLogFormat::Common => entry.write_common(&mut line, response),
+            AccessLogFormat::Combined => entry
+                .write_common(&mut line, response)
+                .and_then(|()| entry.write_combined(&mut line)),
+            AccessLogFormat::Json => {
+                entry.write_json(&mut line, route, response, outcome, duration)
+            }
+        };
+        line.push('\n');
+
+        // A full queue or a failing sink must not hold up or fail the request.
+        if let Some(lines) = &self.lines {
+            let _ = lines.try_send(line);
+        }
+    }
+}
+
+impl Drop for AccessLog {
+    fn drop(&mut self) {
+        // Closing the queue stops the thread once it has written the remaining lines.
+        drop(self.lines.take());
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+fn write_lines(lines: Receiver<String>, writer: impl Write) {
+    let mut writer = BufWriter::new(writer);
+    while let Ok(line) = lines.recv() {
+        let _ = writer.write_all(line.as_bytes());
+        // Flushed once caught up, so lines show up promptly without a write per line under load.
+        while let Ok(line) = lines.try_recv() {
+            let _ = writer.write_all(line.as_bytes());
+        }
+        let _ = writer.flush();
+    }
+}
+
+impl fmt::Debug for AccessLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AccessLog")
+            .field("format", &self.format)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Request details only needed by the access log.
+pub(crate) struct AccessLogEntry {
+    received_at: SystemTime,
+    remote_addr: Option<SocketAddr>,
+    method: String,
+    uri: String,
+    version: String,
+    referer: Option<String>,
+    user_agent: Option<String>,
+}
+
+impl AccessLogEntry {
+    pub(crate) fn new<B>(request: &Request<B>) -> Self {
+        let header = |name| {
+            request
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+
+        Self {
+            received_at: SystemTime::now(),
+            remote_addr: request
+                .extensions()
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|connect_info| connect_info.0),
+            method: request.method().to_string(),
+            uri: request.uri().to_string(),
+            version: format!("{:?}", request.version()),
+            referer: header(REFERER),
+            user_agent: header(USER_AGENT),
+        }
+    }
+
+    fn write_common(&self, line: &mut String, response: Option<&ResponseMetadata>) -> fmt::Result {
+        match self.remote_addr {
+            Some(addr) => write!(line, "{}", addr.ip())?,
+            None => line.push('-'),
+        }
+        line.push_str(" - - [");
+        write_clf_time(line, self.received_at)?;
+        line.push_str("] \"");
+        write_escaped(
+            line,
+            &format!("{} {} {}", self.method, self.uri, self.version),
+        );
+        line.push_str("\" ");
+        match response {
+            Some(response) => write!(line, "{}", response.status().as_u16())?,
+            None => line.push('-'),
+        }
+        match response.and_then(ResponseMetadata::size) {
+            Some(size) => write!(line, " {size}"),
+            None => write!(line, " -"),
+        }
+    }
+
+    fn write_combined(&self, line: &mut String) -> fmt::Result {
+        for value in [&self.referer, &self.user_agent] {
+            line.push_str(" \"");
+            write_escaped(line, value.as_deref().unwrap_or("-"));
+            line.push('"');
+        }
+        Ok(())
+    }
+
+    fn write_json(
+        &self,
+        line: &mut String,
+        route: &str,
+        response: Option<&ResponseMetadata>,
+        outcome: Outcome,
+        duration: Duration,
+    ) -> fmt::Result {
+        line.push_str("{\"time\":\"");
+        write_rfc3339_time(line, self.received_at)?;
+        line.push_str("\",\"remote_addr\":");
+        write_json_string(line, self.remote_addr.map(|addr| addr.ip().to_string()))?;
+        line.push_str(",\"method\":");
+        write_json_string(line, Some(&self.method))?;
+        line.push_str(",\"uri\":");
+        write_json_string(line, Some(&self.uri))?;
+        line.push_str(",\"route\":");
+        write_json_string(line, Some(route))?;
+        line.push_str(",\"version\":");
+        write_json_string(line, Some(&self.version))?;
+        line.push_str(",\"status\":");
+        match response {
+            Some(response) => write!(line, "{}", response.status().as_u16())?,
+            None => line.push_str("null"),
+        }
+        line.push_str(",\"bytes\":");
+        match response.and_then(ResponseMetadata::size) {
+            Some(size) => write!(line, "{size}")?,
+            None => line.push_str("null"),
+        }
+        write!(
+            line,
+            ",\"duration_seconds\":{},\"outcome\":\"{}\",\"referer\":",
+            duration.as_secs_f64(),
+            outcome.as_str()
+        )?;
+        write_json_string(line, self.referer.as_ref())?;
+        line.push_str(",\"user_agent\":");
+        write_json_string(line, self.user_agent.as_ref())?;
+        line.push('}');
+        Ok(())
+    }
+}
+
+/// Escapes quotes, backslashes and control characters in a quoted log field.
+fn write_escaped(line: &mut String, value: &str) {
+    for c in value.chars() {
+        match c {
+            '"' => line.push_str("\\\""),
+            '\\' => line.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(line, "\\x{:02x}", c as u32);
+            }
+            c => line.push(c),
+        }
+    }
+}
+
+fn write_json_string(line: &mut String, value: Option<impl AsRef<str>>) -> fmt::Result {
+    let Some(value) = value else {
+        line.push_str("null");
+        return Ok(());
+    };
+
+    line.push('"');
+    for c in value.as_ref().chars() {
+        match c {
+            '"' => line.push_str("\\\""),
+            '\\' => line.push_str("\\\\"),
+            '\n' => line.push_str("\\n"),
+            '\r' => line.push_str("\\r"),
+            '\t' => line.push_str("\\t"),
+            c if c.is_control() => write!(line, "\\u{:04x}", c as u32)?,
+            c => line.push(c),
+        }
+    }
+    line.push('"');
+    Ok(())
+}
+
+/// UTC date and time of day of a timestamp.
+struct DateTime {
+    year: i64,
+    month: u32,
+    day: u32,
+    hour: u64,
+    minute: u64,
+    second: u64,
+    millis: u32,
+}
+
+impl From<SystemTime> for DateTime {
+    fn from(time: SystemTime) -> Self {
+        let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let seconds = since_epoch.as_secs();
+
+        // Days to civil date, see http://howardhinnant.github.io/date_algorithms.html
+        let days = (seconds / 86400) as i64 + 719468;
+        let era = days / 146097;
+        let day_of_era = days - era * 146097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        } as u32;
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+        Self {
+            year,
+            month,
+            day,
+            hour: seconds % 86400 / 3600,
+            minute: seconds % 3600 / 60,
+            second: seconds % 60,
+            millis: since_epoch.subsec_millis(),
+        }
+    }
+}
+
+fn write_clf_time(line: &mut String, time: SystemTime) -> fmt::Result {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    let time = DateTime::from(time);
+    write!(
+        line,
+        "{:02}/{}/{}:{:02}:{:02}:{:02} +0000",
+        time.day,
+        MONTHS[time.month as usize - 1],
+        time.year,
+        time.hour,
+        time.minute,
+        time.second
+    )
+}
+
+fn write_rfc3339_time(line: &mut String, time: SystemTime) -> fmt::Result {
+    let time = DateTime::from(time);
+    write!(
+        line,
+        "{}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        time.year, time.month, time.day, time.hour, time.minute, time.second, time.millis
+    )
+}
+
+/// File writer rotating to `<path>.1`, `<path>.2`, … once it grows past a size limit.
+#[derive(Debug)]
+pub struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    /// Appends to the file at `path`, keeping at most `max_files` rotated files around.
+    pub fn new(path: impl Into<PathBuf>, max_bytes: u64, max_files: usize) -> io::Result<Self> {
+        let path = path.into();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            path,
+            max_bytes,
+            max_files,
+            file,
+            size,
+        })
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{index}"));
+        path.into()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.max_files == 0 {
+            self.file = File::create(&self.path)?;
+        } else {
+            for index in (1..self.max_files).rev() {
+                let from = self.rotated_path(index);
+                if from.exists() {
+                    fs::rename(from, self.rotated_path(index + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+            self.file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+        }
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::SocketAddr,
+        sync::{Arc, Mutex},
+    };
+
+    use axum::{body::Body, extract::ConnectInfo, http::Response};
+
+    use super::*;
+    use crate::builder::Config;
+
+    /// 2000-02-29T12:34:56.789Z
+    const LEAP_DAY: Duration = Duration::from_millis(951_827_696_789);
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn entry(request: Request<Body>) -> AccessLogEntry {
+        let mut entry = AccessLogEntry::new(&request);
+        entry.received_at = UNIX_EPOCH + LEAP_DAY;
+        entry
+    }
+
+    fn full_request() -> Request<Body> {
+        let mut request = Request::get("/users/1?tab=posts")
+            .header(REFERER, "https://example.com/")
+            .header(USER_AGENT, "curl/8.0 \"test\"")
+            .body(Body::empty())
+            .unwrap();
+        let addr: SocketAddr = "192.0.2.1:4321".parse().unwrap();
+        request.extensions_mut().insert(ConnectInfo(addr));
+        request
+    }
+
+    fn response() -> ResponseMetadata {
+        let response = Response::new(Body::from("hello"));
+        ResponseMetadata::new(&response, &Config::default(), &())
+    }
+
+    /// Logs an entry and returns the written lines, once the access log has been dropped.
+    fn log(
+        format: AccessLogFormat,
+        entry: &AccessLogEntry,
+        response: Option<&ResponseMetadata>,
+        outcome: Outcome,
+    ) -> String {
+        let buffer = Buffer::default();
+        let access_log = AccessLog::new(format, buffer.clone());
+        access_log.log_entry(
+            entry,
+            "/users/:id",
+            response,
+            outcome,
+            Duration::from_millis(250),
+        );
+        drop(access_log);
+        let lines = buffer.0.lock().unwrap().clone();
+        String::from_utf8(lines).unwrap()
+    }
+
+    #[test]
+    fn writes_common_lines() {
+        let line = log(
+            AccessLogFormat::Common,
+            &entry(full_request()),
+            Some(&response()),
+            Outcome::Completed,
+        );
+        assert_eq!(
+            line,
+            "192.0.2.1 - - [29/Feb/2000:12:34:56 +0000] \"GET /users/1?tab=posts HTTP/1.1\" 200 5\n"
+        );
+    }
+
+    #[test]
+    fn writes_combined_lines() {
+        let line = log(
+            AccessLogFormat::Combined,
+            &entry(full_request()),
+            Some(&response()),
+            Outcome::Completed,
+        );
+        assert_eq!(
+            line,
+            "192.0.2.1 - - [29/Feb/2000:12:34:56 +0000] \"GET /users/1?tab=posts HTTP/1.1\" 200 5 \
+             \"https://example.com/\" \"curl/8.0 \\\"test\\\"\"\n"
+        );
+    }
+
+    #[test]
+    fn writes_json_lines() {
+        let line = log(
+            AccessLogFormat::Json,
+            &entry(full_request()),
+            Some(&response()),
+            Outcome::Completed,
+        );
+        assert_eq!(
+            line,
+            "{\"time\":\"2000-02-29T12:34:56.789Z\",\"remote_addr\":\"192.0.2.1\",\
+             \"method\":\"GET\",\"uri\":\"/users/1?tab=posts\",\"route\":\"/users/:id\",\
+             \"version\":\"HTTP/1.1\",\"status\":200,\"bytes\":5,\"duration_seconds\":0.25,\
+             \"outcome\":\"completed\",\"referer\":\"https://example.com/\",\
+             \"user_agent\":\"curl/8.0 \\\"test\\\"\"}\n"
+        );
+    }
+
+    #[test]
+    fn writes_placeholders_for_missing_values() {
+        let request = Request::get("/").body(Body::empty()).unwrap();
+        let entry = entry(request);
+
+        let line = log(AccessLogFormat::Combined, &entry, None, Outcome::Cancelled);
+        assert_eq!(
+            line,
+            "- - - [29/Feb/2000:12:34:56 +0000] \"GET / HTTP/1.1\" - - \"-\" \"-\"\n"
+        );
+
+        let line = log(AccessLogFormat::Json, &entry, None, Outcome::Cancelled);
+        assert_eq!(
+            line,
+            "{\"time\":\"2000-02-29T12:34:56.789Z\",\"remote_addr\":null,\"method\":\"GET\",\
+             \"uri\":\"/\",\"route\":\"/users/:id\",\"version\":\"HTTP/1.1\",\"status\":null,\
+             \"bytes\":null,\"duration_seconds\":0.25,\"outcome\":\"cancelled\",\
+             \"referer\":null,\"user_agent\":null}\n"
+        );
+    }
+
+    #[test]
+    fn rotates_files() {
+        let dir =
+            std::env::temp_dir().join(format!("axum-metrics-rotation-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("access.log");
+
+        let mut file = RotatingFile::new(&path, 10, 2).unwrap();
+        for line in ["aaaaaaa\n", "bbbbbbb\n", "ccccccc\n", "ddddddd\n"] {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+        drop(file);
+
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read("access.log"), "ddddddd\n");
+        assert_eq!(read("access.log.1"), "ccccccc\n");
+        assert_eq!(read("access.log.2"), "bbbbbbb\n");
+        // Only `max_files` rotated files are kept.
+        assert!(!dir.join("access.log.3").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,217 @@
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use axum::{
+    body::HttpBody,
+    http::{header::CONTENT_LENGTH, HeaderMap},
+};
+use bytes::Buf;
+use http_body::{Frame, SizeHint};
+use metrics::{Label, SharedString};
+use pin_project::{pin_project, pinned_drop};
+
+use crate::{
+    builder::Config,
+    metadata::{grpc_status, Completion},
+    DefaultLabel, Metric, Outcome,
+};
+
+/// Body wrapper recording how long a body takes to stream and how many bytes pass through.
+#[pin_project(PinnedDrop)]
+pub struct InstrumentedBody<B> {
+    #[pin]
+    inner: B,
+    state: BodyState,
+}
+
+struct BodyState {
+    timing: Option<BodyTiming>,
+    size: Option<BodySize>,
+    completion: Option<Completion>,
+}
+
+impl BodyState {
+    fn finish(&mut self, outcome: Outcome) {
+        if let Some(timing) = self.timing.take() {
+            timing.finish(outcome);
+        }
+        if let Some(size) = self.size.take() {
+            size.finish();
+        }
+        if let Some(completion) = self.completion.take() {
+            completion.record(outcome);
+        }
+    }
+}
+
+pub(crate) struct BodyTiming {
+    config: Arc<Config>,
+    labels: Vec<Label>,
+    started_at: Instant,
+    headers_at: Instant,
+    first_byte_recorded: bool,
+}
+
+impl BodyTiming {
+    pub(crate) fn new(config: Arc<Config>, labels: Vec<Label>, started_at: Instant) -> Self {
+        Self {
+            config,
+            labels,
+            started_at,
+            headers_at: Instant::now(),
+            first_byte_recorded: false,
+        }
+    }
+
+    fn record_first_byte(&mut self) {
+        if !self.first_byte_recorded {
+            self.first_byte_recorded = true;
+            metrics::histogram!(
+                self.config.name(Metric::ResponseFirstByte),
+                self.labels.clone()
+            )
+            .record(self.started_at.elapsed().as_secs_f64());
+        }
+    }
+
+    fn finish(mut self, outcome: Outcome) {
+        if outcome == Outcome::Completed {
+            // Empty bodies never yield a data frame, so the first byte is the end of stream.
+            self.record_first_byte();
+        }
+
+        let config = self.config;
+        if outcome != Outcome::Completed && !config.time_failures {
+            return;
+        }
+
+        let mut labels = self.labels;
+        config.push_label(&mut labels, DefaultLabel::Outcome, outcome.as_str());
+        metrics::histogram!(config.name(Metric::ResponseBodyDuration), labels.clone())
+            .record(self.headers_at.elapsed().as_secs_f64());
+        metrics::histogram!(config.name(Metric::ResponseDuration), labels)
+            .record(self.started_at.elapsed().as_secs_f64());
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct BodySize {
+    name: SharedString,
+    labels: Vec<Label>,
+    bytes: u64,
+}
+
+impl BodySize {
+    pub(crate) fn new(config: &Config, metric: Metric, labels: Vec<Label>) -> Self {
+        Self {
+            name: config.name(metric),
+            labels,
+            bytes: 0,
+        }
+    }
+
+    fn finish(self) {
+        metrics::histogram!(self.name, self.labels).record(self.bytes as f64);
+    }
+}
+
+/// Returns the size of a body from its `Content-Length` header or exact size hint, if known.
+pub(crate) fn known_size(headers: &HeaderMap, body: &impl HttpBody) -> Option<u64> {
+    headers
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .or_else(|| body.size_hint().exact())
+}
+
+impl<B> InstrumentedBody<B>
+where
+    B: HttpBody,
+{
+    pub(crate) fn new(
+        inner: B,
+        timing: Option<BodyTiming>,
+        size: Option<BodySize>,
+        completion: Option<Completion>,
+    ) -> Self {
+        let mut state = BodyState {
+            timing,
+            size,
+            completion,
+        };
+
+        // Hyper never polls a body that is already at the end of the stream.
+        if inner.is_end_stream() {
+            state.finish(Outcome::Completed);
+        }
+
+        Self { inner, state }
+    }
+}
+
+#[pinned_drop]
+impl<B> PinnedDrop for InstrumentedBody<B> {
+    fn drop(self: Pin<&mut Self>) {
+        self.project().state.finish(Outcome::Cancelled);
+    }
+}
+
+impl<B> HttpBody for InstrumentedBody<B>
+where
+    B: HttpBody,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let mut this = self.project();
+        let result = match this.inner.as_mut().poll_frame(cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+
+        let state = this.state;
+        match &result {
+            Some(Ok(frame)) => {
+                if let Some(data) = frame.data_ref() {
+                    if let Some(timing) = state.timing.as_mut() {
+                        timing.record_first_byte();
+                    }
+                    if let Some(size) = state.size.as_mut() {
+                        size.bytes += data.remaining() as u64;
+                    }
+                }
+                if let Some(trailers) = frame.trailers_ref() {
+                    if let (Some(completion), Some(status)) =
+                        (state.completion.as_mut(), grpc_status(trailers))
+                    {
+                        completion.set_grpc_status(status);
+                    }
+                }
+                // Hyper stops polling once the body reports the end of the stream.
+                if this.inner.is_end_stream() {
+                    state.finish(Outcome::Completed);
+                }
+            }
+            Some(Err(_)) => state.finish(Outcome::Error),
+            None => state.finish(Outcome::Completed),
+        }
+
+        Poll::Ready(result)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
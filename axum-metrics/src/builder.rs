@@ -0,0 +1,820 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    sync::{Arc, Once},
+};
+
+use axum::http::{Method, StatusCode};
+use metrics::{Label, SharedString, Unit};
+
+use crate::{
+    rules::Rules, AccessLog, MetricLayer, CONNECTIONS_ACCEPTED_TOTAL, CONNECTIONS_OPEN,
+    CONNECTION_DURATION_SECONDS, CONNECTION_REQUESTS, CONNECTION_REUSED_REQUESTS_TOTAL,
+    DEFAULT_COUNT_BUCKETS, DEFAULT_SIZE_BUCKETS, ERRORS_TOTAL, REQUESTS_DURATION_SECONDS,
+    REQUESTS_IN_FLIGHT, REQUESTS_SCHEDULE_DELAY_SECONDS, REQUESTS_TOTAL, REQUEST_SIZE_BYTES,
+    RESPONSE_BODY_DURATION_SECONDS, RESPONSE_DURATION_SECONDS, RESPONSE_FIRST_BYTE_SECONDS,
+    RESPONSE_SIZE_BYTES,
+};
+
+/// Metrics recorded by [`MetricLayer`], and by [`ConnectionMetrics`](crate::ConnectionMetrics)
+/// for the `Connection*` ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Metric {
+    RequestsTotal,
+    RequestsDuration,
+    RequestsInFlight,
+    RequestsScheduleDelay,
+    ResponseDuration,
+    ResponseFirstByte,
+    ResponseBodyDuration,
+    RequestSize,
+    ResponseSize,
+    ErrorsTotal,
+    ConnectionsAccepted,
+    ConnectionsOpen,
+    ConnectionDuration,
+    ConnectionRequests,
+    ConnectionReusedRequests,
+}
+
+impl Metric {
+    pub(crate) const ALL: [Metric; 15] = [
+        Metric::RequestsTotal,
+        Metric::RequestsDuration,
+        Metric::RequestsInFlight,
+        Metric::RequestsScheduleDelay,
+        Metric::ResponseDuration,
+        Metric::ResponseFirstByte,
+        Metric::ResponseBodyDuration,
+        Metric::RequestSize,
+        Metric::ResponseSize,
+        Metric::ErrorsTotal,
+        Metric::ConnectionsAccepted,
+        Metric::ConnectionsOpen,
+        Metric::ConnectionDuration,
+        Metric::ConnectionRequests,
+        Metric::ConnectionReusedRequests,
+    ];
+
+    pub fn default_name(&self) -> &'static str {
+        match self {
+            Metric::RequestsTotal => REQUESTS_TOTAL,
+            Metric::RequestsDuration => REQUESTS_DURATION_SECONDS,
+            Metric::RequestsInFlight => REQUESTS_IN_FLIGHT,
+            Metric::RequestsScheduleDelay => REQUESTS_SCHEDULE_DELAY_SECONDS,
+            Metric::ResponseDuration => RESPONSE_DURATION_SECONDS,
+            Metric::ResponseFirstByte => RESPONSE_FIRST_BYTE_SECONDS,
+            Metric::ResponseBodyDuration => RESPONSE_BODY_DURATION_SECONDS,
+            Metric::RequestSize => REQUEST_SIZE_BYTES,
+            Metric::ResponseSize => RESPONSE_SIZE_BYTES,
+            Metric::ErrorsTotal => ERRORS_TOTAL,
+            Metric::ConnectionsAccepted => CONNECTIONS_ACCEPTED_TOTAL,
+            Metric::ConnectionsOpen => CONNECTIONS_OPEN,
+            Metric::ConnectionDuration => CONNECTION_DURATION_SECONDS,
+            Metric::ConnectionRequests => CONNECTION_REQUESTS,
+            Metric::ConnectionReusedRequests => CONNECTION_REUSED_REQUESTS_TOTAL,
+        }
+    }
+
+    /// Name under the OpenTelemetry HTTP semantic conventions.
+    ///
+    /// `None` for [`Metric::RequestsTotal`] and [`Metric::ErrorsTotal`], which the conventions
+    /// leave to the count of the duration histogram and its `error.type` attribute.
+    ///
+    /// The request and response duration, active request and body size names are the standard
+    /// ones. The conventions define none of the others, which are named in the same
+    /// `http.server.*` or `http.client.*` namespace: the schedule delay, response duration, first
+    /// byte and body duration histograms, and all the connection metrics.
+    pub fn otel_name(&self, client: bool) -> Option<&'static str> {
+        let name = match (self, client) {
+            (Metric::RequestsTotal | Metric::ErrorsTotal, _) => return None,
+            (Metric::RequestsDuration, false) => "http.server.request.duration",
+            (Metric::RequestsDuration, true) => "http.client.request.duration",
+            (Metric::RequestsInFlight, false) => "http.server.active_requests",
+            (Metric::RequestsInFlight, true) => "http.client.active_requests",
+            (Metric::RequestsScheduleDelay, false) => "http.server.request.schedule_delay",
+            (Metric::RequestsScheduleDelay, true) => "http.client.request.schedule_delay",
+            (Metric::ResponseDuration, false) => "http.server.response.duration",
+            (Metric::ResponseDuration, true) => "http.client.response.duration",
+            (Metric::ResponseFirstByte, false) => "http.server.response.first_byte",
+            (Metric::ResponseFirstByte, true) => "http.client.response.first_byte",
+            (Metric::ResponseBodyDuration, false) => "http.server.response.body.duration",
+            (Metric::ResponseBodyDuration, true) => "http.client.response.body.duration",
+            (Metric::RequestSize, false) => "http.server.request.body.size",
+            (Metric::RequestSize, true) => "http.client.request.body.size",
+            (Metric::ResponseSize, false) => "http.server.response.body.size",
+            (Metric::ResponseSize, true) => "http.client.response.body.size",
+            // Connections are only recorded on the server side.
+            (Metric::ConnectionsAccepted, _) => "http.server.connection.accepted",
+            (Metric::ConnectionsOpen, _) => "http.server.open_connections",
+            (Metric::ConnectionDuration, _) => "http.server.connection.duration",
+            (Metric::ConnectionRequests, _) => "http.server.connection.requests",
+            (Metric::ConnectionReusedRequests, _) => "http.server.connection.reused_requests",
+        };
+        Some(name)
+    }
+
+    /// Whether the metric is a histogram of durations in seconds.
+    pub(crate) fn is_duration(&self) -> bool {
+        matches!(
+            self,
+            Metric::RequestsDuration
+                | Metric::RequestsScheduleDelay
+                | Metric::ResponseDuration
+                | Metric::ResponseFirstByte
+                | Metric::ResponseBodyDuration
+        )
+    }
+
+    pub(crate) fn unit(&self) -> Unit {
+        match self {
+            Metric::RequestSize | Metric::ResponseSize => Unit::Bytes,
+            Metric::RequestsDuration
+            | Metric::RequestsScheduleDelay
+            | Metric::ResponseDuration
+            | Metric::ResponseFirstByte
+            | Metric::ResponseBodyDuration
+            | Metric::ConnectionDuration => Unit::Seconds,
+            _ => Unit::Count,
+        }
+    }
+
+    pub(crate) fn description(&self) -> &'static str {
+        match self {
+            Metric::RequestsTotal => "Number of requests.",
+            Metric::RequestsDuration => "Duration of requests.",
+            Metric::RequestsInFlight => "Number of requests being handled.",
+            Metric::RequestsScheduleDelay => {
+                "Delay between a request being dispatched and first polled."
+            }
+            Metric::ResponseDuration => "Time until the response body was fully sent.",
+            Metric::ResponseFirstByte => "Time until the first byte of the response body.",
+            Metric::ResponseBodyDuration => "Time taken to stream the response body.",
+            Metric::RequestSize => "Size of request bodies.",
+            Metric::ResponseSize => "Size of response bodies.",
+            Metric::ErrorsTotal => "Number of errors returned by the inner service.",
+            Metric::ConnectionsAccepted => "Number of accepted connections.",
+            Metric::ConnectionsOpen => "Number of open connections.",
+            Metric::ConnectionDuration => "Lifetime of connections.",
+            Metric::ConnectionRequests => "Number of requests served by a connection.",
+            Metric::ConnectionReusedRequests => {
+                "Number of requests served by a kept-alive connection after its first one."
+            }
+        }
+    }
+
+    /// Name used by client layers, with `http_client_` in place of `http_server_`.
+    pub fn default_client_name(&self) -> String {
+        self.default_name()
+            .replacen("http_server_", "http_client_", 1)
+    }
+}
+
+/// Labels attached by [`MetricLayer`] unless disabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DefaultLabel {
+    Method,
+    Path,
+    Status,
+    StatusClass,
+    Error,
+    ErrorKind,
+    GrpcService,
+    GrpcMethod,
+    GrpcStatus,
+    Host,
+    Operation,
+    Outcome,
+    /// HTTP version of the request, attached to request metrics only under the OpenTelemetry
+    /// conventions and always to connection metrics.
+    ProtocolVersion,
+    /// URI scheme of the request, only attached under the OpenTelemetry conventions and when the
+    /// scheme is known, see [`MetricLayerBuilder::scheme`].
+    Scheme,
+}
+
+impl DefaultLabel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DefaultLabel::Method => "method",
+            DefaultLabel::Path => "path",
+            DefaultLabel::Status => "status",
+            DefaultLabel::StatusClass => "status_class",
+            DefaultLabel::Error => "error",
+            DefaultLabel::ErrorKind => "error_kind",
+            DefaultLabel::GrpcService => "grpc_service",
+            DefaultLabel::GrpcMethod => "grpc_method",
+            DefaultLabel::GrpcStatus => "grpc_status",
+            DefaultLabel::Host => "host",
+            DefaultLabel::Operation => "operation",
+            DefaultLabel::Outcome => "outcome",
+            DefaultLabel::ProtocolVersion => "protocol_version",
+            DefaultLabel::Scheme => "scheme",
+        }
+    }
+
+    /// Attribute name under the OpenTelemetry HTTP semantic conventions.
+    ///
+    /// The conventions define no `status_class`, `error`, `operation` or `outcome` attribute,
+    /// these keep their default names.
+    pub fn otel_name(&self) -> &'static str {
+        match self {
+            DefaultLabel::Method => "http.request.method",
+            DefaultLabel::Path => "http.route",
+            DefaultLabel::Status => "http.response.status_code",
+            DefaultLabel::ErrorKind => "error.type",
+            DefaultLabel::GrpcService => "rpc.service",
+            DefaultLabel::GrpcMethod => "rpc.method",
+            DefaultLabel::GrpcStatus => "rpc.grpc.status_code",
+            DefaultLabel::Host => "server.address",
+            DefaultLabel::ProtocolVersion => "network.protocol.version",
+            DefaultLabel::Scheme => "url.scheme",
+            label => label.as_str(),
+        }
+    }
+}
+
+/// Naming scheme of metrics and labels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Conventions {
+    /// `http_server_*` metrics labelled by `method`, `path`, `status`, ….
+    #[default]
+    Default,
+    /// OpenTelemetry HTTP semantic conventions, e.g. `http.server.request.duration` with
+    /// `http.request.method`, `http.route` and `http.response.status_code` attributes.
+    ///
+    /// Methods outside the standard ones are reported as `_OTHER`, and the `outcome` label is
+    /// disabled unless enabled with [`MetricLayerBuilder::default_label`]. Status codes are
+    /// always exact, any [`StatusGranularity`] counts as [`StatusGranularity::Code`].
+    ///
+    /// The request and error counters are not recorded unless named with
+    /// [`MetricLayerBuilder::metric_name`]. Requests are counted by the duration histogram
+    /// instead, where failed ones carry an `error.type`: the error kind, the status code of 5xx
+    /// responses, or `cancelled`. See [`Metric::otel_name`] for the metrics outside the
+    /// conventions.
+    OpenTelemetry,
+}
+
+/// How response status codes are labelled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StatusGranularity {
+    /// The exact code in `status`, e.g. `404`.
+    #[default]
+    Code,
+    /// The class of the code in `status`, e.g. `4xx`.
+    Class,
+    /// The exact code in `status` and its class in `status_class`.
+    Both,
+}
+
+/// Upper bounds of the buckets of a histogram.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Buckets(Vec<f64>);
+
+impl Buckets {
+    /// # Panics
+    ///
+    /// Panics if `bounds` is empty, or not finite and strictly increasing.
+    pub fn new(bounds: impl Into<Vec<f64>>) -> Self {
+        let bounds = bounds.into();
+        // Exporters expect sorted, distinct bounds, and an infinite one is implied.
+        assert!(
+            !bounds.is_empty()
+                && bounds.iter().all(|bound| bound.is_finite())
+                && bounds.windows(2).all(|pair| pair[0] < pair[1]),
+            "bucket bounds must be finite and strictly increasing, got {bounds:?}"
+        );
+        Self(bounds)
+    }
+
+    /// `count` buckets starting at `start`, each `factor` times wider than the previous one.
+    ///
+    /// # Panics
+    ///
+    /// Panics unless `count` is at least 1, `start` is positive and `factor` greater than 1.
+    pub fn exponential(start: f64, factor: f64, count: usize) -> Self {
+        Self::new(
+            std::iter::successors(Some(start), |bound| Some(bound * factor))
+                .take(count)
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    /// `count` buckets starting at `start`, each `width` wide.
+    ///
+    /// # Panics
+    ///
+    /// Panics unless `count` is at least 1 and `width` is positive.
+    pub fn linear(start: f64, width: f64, count: usize) -> Self {
+        Self::new(
+            (0..count)
+                .map(|index| start + width * index as f64)
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    pub fn as_slice(&self) -> &[f64] {
+        &self.0
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct ErrorClassifier(Arc<dyn Fn(StatusCode) -> bool + Send + Sync>);
+
+impl ErrorClassifier {
+    pub(crate) fn is_error(&self, status: StatusCode) -> bool {
+        (self.0)(status)
+    }
+}
+
+impl Default for ErrorClassifier {
+    fn default() -> Self {
+        Self(Arc::new(|status| status.is_server_error()))
+    }
+}
+
+impl fmt::Debug for ErrorClassifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ErrorClassifier").finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Config {
+    pub(crate) time_failures: bool,
+    pub(crate) unmatched_path: String,
+    pub(crate) track_body: bool,
+    pub(crate) schedule_delay: bool,
+    pub(crate) body_sizes: bool,
+    pub(crate) grpc: bool,
+    pub(crate) client: bool,
+    pub(crate) operation: Option<String>,
+    pub(crate) scheme: Option<String>,
+    pub(crate) status_granularity: StatusGranularity,
+    pub(crate) error_classifier: Option<ErrorClassifier>,
+    pub(crate) access_log: Option<Arc<AccessLog>>,
+    pub(crate) handler_labels: HashSet<String>,
+    pub(crate) rules: Rules,
+    pub(crate) conventions: Conventions,
+    names: HashMap<Metric, SharedString>,
+    pub(crate) buckets: Vec<(SharedString, Buckets)>,
+    pub(crate) route_buckets: Vec<(String, Buckets)>,
+    constant_labels: Vec<Label>,
+    disabled_labels: HashSet<DefaultLabel>,
+    described: Arc<Once>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            time_failures: true,
+            unmatched_path: "<unmatched>".to_string(),
+            track_body: false,
+            schedule_delay: false,
+            body_sizes: true,
+            grpc: false,
+            client: false,
+            operation: None,
+            scheme: None,
+            status_granularity: StatusGranularity::default(),
+            error_classifier: None,
+            access_log: None,
+            handler_labels: HashSet::new(),
+            rules: Rules::default(),
+            conventions: Conventions::default(),
+            names: HashMap::new(),
+            buckets: Vec::new(),
+            route_buckets: Vec::new(),
+            constant_labels: Vec::new(),
+            disabled_labels: HashSet::new(),
+            described: Arc::new(Once::new()),
+        }
+    }
+}
+
+impl Config {
+    pub(crate) fn name(&self, metric: Metric) -> SharedString {
+        self.names[&metric].clone()
+    }
+
+    /// Name of a metric, unless it is not recorded under the conventions.
+    pub(crate) fn recorded_name(&self, metric: Metric) -> Option<SharedString> {
+        self.names.get(&metric).cloned()
+    }
+
+    /// Reports the unit and description of every metric to the recorder, once per layer.
+    ///
+    /// Deferred to the first request so that the recorder is installed by then.
+    pub(crate) fn describe(&self) {
+        self.described.call_once(|| {
+            for metric in Metric::ALL {
+                let Some(name) = self.recorded_name(metric) else {
+                    continue;
+                };
+                let description = metric.description();
+                match metric {
+                    Metric::RequestsTotal
+                    | Metric::ErrorsTotal
+                    | Metric::ConnectionsAccepted
+                    | Metric::ConnectionReusedRequests => {
+                        metrics::describe_counter!(name, metric.unit(), description)
+                    }
+                    Metric::RequestsInFlight | Metric::ConnectionsOpen => {
+                        metrics::describe_gauge!(name, metric.unit(), description)
+                    }
+                    _ => metrics::describe_histogram!(name, metric.unit(), description),
+                }
+            }
+        });
+    }
+
+    pub(crate) fn name_str(&self, metric: Metric) -> &str {
+        &self.names[&metric]
+    }
+
+    pub(crate) fn constant_labels(&self) -> Vec<Label> {
+        self.constant_labels.clone()
+    }
+
+    /// Key of one of the default labels, unless it is disabled.
+    pub(crate) fn label_key(&self, label: DefaultLabel) -> Option<&'static str> {
+        if self.disabled_labels.contains(&label) {
+            return None;
+        }
+        Some(match self.conventions {
+            Conventions::Default => label.as_str(),
+            Conventions::OpenTelemetry => label.otel_name(),
+        })
+    }
+
+    pub(crate) fn push_label(
+        &self,
+        labels: &mut Vec<Label>,
+        label: DefaultLabel,
+        value: impl Into<SharedString>,
+    ) {
+        if let Some(key) = self.label_key(label) {
+            labels.push(Label::new(key, value));
+        }
+    }
+}
+
+/// Builder for [`MetricLayer`], created with [`MetricLayer::builder`].
+#[derive(Debug, Clone)]
+pub struct MetricLayerBuilder<RL = (), SL = (), EC = ()> {
+    config: Config,
+    prefix: Option<String>,
+    names: HashMap<Metric, String>,
+    buckets: HashMap<Metric, Buckets>,
+    route_buckets: Vec<(String, Buckets)>,
+    explicit_labels: HashSet<DefaultLabel>,
+    request_labeler: RL,
+    response_labeler: SL,
+    error_kind_classifier: EC,
+}
+
+impl Default for MetricLayerBuilder {
+    fn default() -> Self {
+        Self {
+            config: Config::default(),
+            prefix: None,
+            names: HashMap::new(),
+            buckets: HashMap::new(),
+            route_buckets: Vec::new(),
+            explicit_labels: HashSet::new(),
+            request_labeler: (),
+            response_labeler: (),
+            error_kind_classifier: (),
+        }
+    }
+}
+
+impl MetricLayerBuilder {
+    pub(crate) fn client() -> Self {
+        let mut builder = Self::default();
+        builder.config.client = true;
+        builder
+    }
+}
+
+impl<RL, SL, EC> MetricLayerBuilder<RL, SL, EC> {
+    /// Whether to record durations for requests that errored or were cancelled.
+    pub fn time_failures(mut self, enabled: bool) -> Self {
+        self.config.time_failures = enabled;
+        self
+    }
+
+    /// Path label used for requests that did not match any route.
+    pub fn unmatched_path(mut self, path: impl Into<String>) -> Self {
+        self.config.unmatched_path = path.into();
+        self
+    }
+
+    /// Whether to wrap response bodies to time how long they take to stream.
+    pub fn track_body(mut self, enabled: bool) -> Self {
+        self.config.track_body = enabled;
+        self
+    }
+
+    /// Whether to record the delay between the request being dispatched and first polled.
+    pub fn schedule_delay(mut self, enabled: bool) -> Self {
+        self.config.schedule_delay = enabled;
+        self
+    }
+
+    /// Whether to record request and response body sizes.
+    ///
+    /// Response bodies without a `Content-Length` are wrapped to count the bytes streamed through
+    /// them. Request bodies are only counted that way with a
+    /// [`RequestBodySizeLayer`](crate::RequestBodySizeLayer) inside of the layer.
+    pub fn body_sizes(mut self, enabled: bool) -> Self {
+        self.config.body_sizes = enabled;
+        self
+    }
+
+    /// Operation label of requests made through a client layer, unless overridden by an
+    /// [`Operation`](crate::Operation) request extension.
+    pub fn operation(mut self, operation: impl Into<String>) -> Self {
+        self.config.operation = Some(operation.into());
+        self
+    }
+
+    /// URI scheme of requests whose URI has none, e.g. `https` behind a TLS listener.
+    ///
+    /// Servers usually only see the path of the URI, so `url.scheme` is left out unless set.
+    pub fn scheme(mut self, scheme: impl Into<String>) -> Self {
+        self.config.scheme = Some(scheme.into());
+        self
+    }
+
+    /// Whether to label requests as gRPC calls.
+    ///
+    /// Requests are labelled by `grpc_service` and `grpc_method` instead of `method` and `path`,
+    /// and completed calls by their `grpc_status`. As the status is usually sent in the trailers,
+    /// request durations then cover the whole response body.
+    pub fn grpc(mut self, enabled: bool) -> Self {
+        self.config.grpc = enabled;
+        self
+    }
+
+    /// How response status codes are labelled, by exact code by default.
+    pub fn status_granularity(mut self, granularity: StatusGranularity) -> Self {
+        self.config.status_granularity = granularity;
+        self
+    }
+
+    /// Whether to add an `error` label, by default `true` for 5xx responses.
+    pub fn error_label(mut self, enabled: bool) -> Self {
+        self.config.error_classifier = enabled.then(ErrorClassifier::default);
+        self
+    }
+
+    /// Adds an `error` label set to `true` for the statuses the classifier considers failures.
+    pub fn error_classifier<F>(mut self, classifier: F) -> Self
+    where
+        F: Fn(StatusCode) -> bool + Send + Sync + 'static,
+    {
+        self.config.error_classifier = Some(ErrorClassifier(Arc::new(classifier)));
+        self
+    }
+
+    /// Writes a line to the [`AccessLog`] for every request.
+    pub fn access_log(mut self, access_log: AccessLog) -> Self {
+        self.config.access_log = Some(Arc::new(access_log));
+        self
+    }
+
+    /// Only records requests to this route, along with any other included one.
+    pub fn include_path(mut self, path: impl Into<String>) -> Self {
+        self.config.rules.include_paths.insert(path.into());
+        self
+    }
+
+    /// Does not record requests to this route, e.g. health checks or metric scrapes.
+    pub fn exclude_path(mut self, path: impl Into<String>) -> Self {
+        self.config.rules.exclude_paths.insert(path.into());
+        self
+    }
+
+    /// Does not record requests with this method, e.g. `OPTIONS`.
+    pub fn exclude_method(mut self, method: Method) -> Self {
+        self.config.rules.exclude_methods.insert(method);
+        self
+    }
+
+    /// Only records requests for which the predicate, given the method and route, returns `true`.
+    pub fn filter<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&Method, &str) -> bool + Send + Sync + 'static,
+    {
+        self.config.rules.predicates.push(Arc::new(predicate));
+        self
+    }
+
+    /// Fraction of requests whose histograms are recorded, `1.0` by default.
+    ///
+    /// Counters and the in-flight gauge still see every request so rates stay accurate.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rate` is not between `0.0` and `1.0`.
+    pub fn sample_rate(mut self, rate: f64) -> Self {
+        self.config.rules.sample_rate = check_sample_rate(rate);
+        self
+    }
+
+    /// Fraction of requests to this route whose histograms are recorded, e.g. for a hot route.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rate` is not between `0.0` and `1.0`.
+    pub fn route_sample_rate(mut self, route: impl Into<String>, rate: f64) -> Self {
+        self.config
+            .rules
+            .route_sample_rates
+            .insert(route.into(), check_sample_rate(rate));
+        self
+    }
+
+    /// Naming scheme of metrics and labels, e.g. the OpenTelemetry semantic conventions.
+    pub fn conventions(mut self, conventions: Conventions) -> Self {
+        self.config.conventions = conventions;
+        self
+    }
+
+    /// Prefix prepended, followed by `_` (or `.` under the OpenTelemetry conventions), to the
+    /// name of every metric.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    /// Overrides the name of a single metric. The prefix is still applied.
+    pub fn metric_name(mut self, metric: Metric, name: impl Into<String>) -> Self {
+        self.names.insert(metric, name.into());
+        self
+    }
+
+    /// Buckets of one of the histograms, applied by the exporter the layer is registered with.
+    ///
+    /// The body sizes default to [`DEFAULT_SIZE_BUCKETS`](crate::DEFAULT_SIZE_BUCKETS), the
+    /// requests per connection to [`DEFAULT_COUNT_BUCKETS`](crate::DEFAULT_COUNT_BUCKETS) and the
+    /// durations to the buckets of the exporter.
+    pub fn buckets(mut self, metric: Metric, buckets: Buckets) -> Self {
+        self.buckets.insert(metric, buckets);
+        self
+    }
+
+    /// Buckets of the duration histograms of a single route, e.g. a slow report endpoint.
+    ///
+    /// The series of the route keep their names and are told apart by their `path` label, so
+    /// these only apply with exporters bucketing series individually, such as the OTLP one.
+    /// Prometheus buckets every series of a metric the same way.
+    pub fn route_buckets(mut self, route: impl Into<String>, buckets: Buckets) -> Self {
+        self.route_buckets.push((route.into(), buckets));
+        self
+    }
+
+    /// Adds a label attached to every sample, e.g. the service name or region.
+    pub fn constant_label(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.config
+            .constant_labels
+            .push(Label::new(key.into(), value.into()));
+        self
+    }
+
+    /// Keys of the [`MetricLabels`](crate::MetricLabels) set by handlers that are added to the
+    /// metrics of their request. Other keys are dropped to keep cardinality in check.
+    pub fn handler_labels<I>(mut self, keys: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.config
+            .handler_labels
+            .extend(keys.into_iter().map(Into::into));
+        self
+    }
+
+    /// Enables or disables one of the labels attached by default.
+    pub fn default_label(mut self, label: DefaultLabel, enabled: bool) -> Self {
+        self.explicit_labels.insert(label);
+        if enabled {
+            self.config.disabled_labels.remove(&label);
+        } else {
+            self.config.disabled_labels.insert(label);
+        }
+        self
+    }
+
+    /// Sets the [`RequestLabeler`](crate::RequestLabeler) adding labels derived from requests.
+    pub fn request_labeler<L>(self, labeler: L) -> MetricLayerBuilder<L, SL, EC> {
+        MetricLayerBuilder {
+            config: self.config,
+            prefix: self.prefix,
+            names: self.names,
+            buckets: self.buckets,
+            route_buckets: self.route_buckets,
+            explicit_labels: self.explicit_labels,
+            request_labeler: labeler,
+            response_labeler: self.response_labeler,
+            error_kind_classifier: self.error_kind_classifier,
+        }
+    }
+
+    /// Sets the [`ResponseLabeler`](crate::ResponseLabeler) adding labels derived from responses.
+    pub fn response_labeler<L>(self, labeler: L) -> MetricLayerBuilder<RL, L, EC> {
+        MetricLayerBuilder {
+            config: self.config,
+            prefix: self.prefix,
+            names: self.names,
+            buckets: self.buckets,
+            route_buckets: self.route_buckets,
+            explicit_labels: self.explicit_labels,
+            request_labeler: self.request_labeler,
+            response_labeler: labeler,
+            error_kind_classifier: self.error_kind_classifier,
+        }
+    }
+
+    /// Sets the [`ErrorKindClassifier`](crate::ErrorKindClassifier) deriving the `error_kind`
+    /// label of errors returned by the inner service.
+    pub fn error_kind_classifier<C>(self, classifier: C) -> MetricLayerBuilder<RL, SL, C> {
+        MetricLayerBuilder {
+            config: self.config,
+            prefix: self.prefix,
+            names: self.names,
+            buckets: self.buckets,
+            route_buckets: self.route_buckets,
+            explicit_labels: self.explicit_labels,
+            request_labeler: self.request_labeler,
+            response_labeler: self.response_labeler,
+            error_kind_classifier: classifier,
+        }
+    }
+
+    pub fn build(self) -> MetricLayer<RL, SL, EC> {
+        let mut config = self.config;
+        // Layers built from clones of a builder have their own names to describe.
+        config.described = Arc::new(Once::new());
+        let otel = config.conventions == Conventions::OpenTelemetry;
+        if otel {
+            if !self.explicit_labels.contains(&DefaultLabel::Outcome) {
+                config.disabled_labels.insert(DefaultLabel::Outcome);
+            }
+            // `http.response.status_code` holds the exact code, and there is no class attribute.
+            if config.status_granularity != StatusGranularity::Code {
+                config.status_granularity = StatusGranularity::Code;
+            }
+        }
+        config.names = Metric::ALL
+            .into_iter()
+            .filter_map(|metric| {
+                let name = match self.names.get(&metric) {
+                    Some(name) => name.clone(),
+                    None if otel => metric.otel_name(config.client)?.to_string(),
+                    None if config.client => metric.default_client_name(),
+                    None => metric.default_name().to_string(),
+                };
+                let name = match &self.prefix {
+                    Some(prefix) if otel => format!("{prefix}.{name}"),
+                    Some(prefix) => format!("{prefix}_{name}"),
+                    None => name,
+                };
+                Some((metric, SharedString::from(Arc::<str>::from(name))))
+            })
+            .collect();
+
+        let mut buckets = self.buckets;
+        for (metric, default) in [
+            (Metric::RequestSize, DEFAULT_SIZE_BUCKETS),
+            (Metric::ResponseSize, DEFAULT_SIZE_BUCKETS),
+            (Metric::ConnectionRequests, DEFAULT_COUNT_BUCKETS),
+        ] {
+            buckets
+                .entry(metric)
+                .or_insert_with(|| Buckets::new(default));
+        }
+        config.buckets = buckets
+            .into_iter()
+            .map(|(metric, buckets)| (config.name(metric), buckets))
+            .collect();
+        config.route_buckets = self.route_buckets;
+
+        MetricLayer {
+            config: Arc::new(config),
+            request_labeler: Arc::new(self.request_labeler),
+            response_labeler: Arc::new(self.response_labeler),
+            error_kind_classifier: Arc::new(self.error_kind_classifier),
+        }
+    }
+}
+
+fn check_sample_rate(rate: f64) -> f64 {
+    // Also rejects NaN, which would silently disable sampling.
+    assert!(
+        (0.0..=1.0).contains(&rate),
+        "sample rate must be between 0.0 and 1.0, got {rate}"
+    );
+    rate
+}
@@ -0,0 +1,201 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, OnceLock,
+    },
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use axum::{
+    http::{Request, Version},
+    serve::IncomingStream,
+};
+use metrics::Label;
+use pin_project::pin_project;
+use tower::Service;
+
+use crate::{builder::Config, DefaultLabel, Metric, MetricLayer};
+
+const UNKNOWN_VERSION: &str = "unknown";
+
+/// Wraps the make service passed to [`axum::serve`] to record connection level metrics.
+///
+/// Open connections, connection lifetimes, requests per connection and reused keep-alive
+/// connections are labelled by the HTTP `protocol_version` of their first request. A connection
+/// is considered closed once hyper drops its service.
+///
+/// ```ignore
+/// let layer = MetricLayer::builder().prefix("api").build();
+/// let app = Router::new().route("/", get(root)).layer(layer.clone());
+/// axum::serve(listener, ConnectionMetrics::with_layer(app.into_make_service(), &layer)).await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct ConnectionMetrics<M> {
+    make_service: M,
+    config: Arc<Config>,
+}
+
+impl<M> ConnectionMetrics<M> {
+    /// Records the connection metrics under their default names.
+    pub fn new(make_service: M) -> Self {
+        Self::with_layer(make_service, &MetricLayer::default())
+    }
+
+    /// Names and labels the connection metrics like the metrics of `layer`, applying its prefix,
+    /// metric names, constant labels and conventions.
+    pub fn with_layer<RL, SL, EC>(make_service: M, layer: &MetricLayer<RL, SL, EC>) -> Self {
+        Self {
+            make_service,
+            config: layer.config.clone(),
+        }
+    }
+}
+
+impl<'a, M> Service<IncomingStream<'a>> for ConnectionMetrics<M>
+where
+    M: Service<IncomingStream<'a>>,
+{
+    type Response = ConnectionService<M::Response>;
+    type Error = M::Error;
+    type Future = ConnectionFuture<M::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.make_service.poll_ready(cx)
+    }
+
+    fn call(&mut self, stream: IncomingStream<'a>) -> Self::Future {
+        let config = &self.config;
+        config.describe();
+        metrics::counter!(
+            config.name(Metric::ConnectionsAccepted),
+            config.constant_labels()
+        )
+        .increment(1);
+        metrics::gauge!(
+            config.name(Metric::ConnectionsOpen),
+            version_labels(config, UNKNOWN_VERSION)
+        )
+        .increment(1);
+
+        ConnectionFuture {
+            future: self.make_service.call(stream),
+            state: Some(Arc::new(ConnectionState {
+                config: config.clone(),
+                accepted_at: Instant::now(),
+                requests: AtomicU64::new(0),
+                version: OnceLock::new(),
+            })),
+        }
+    }
+}
+
+#[pin_project]
+pub struct ConnectionFuture<F> {
+    #[pin]
+    future: F,
+    state: Option<Arc<ConnectionState>>,
+}
+
+impl<F, S, E> Future for ConnectionFuture<F>
+where
+    F: Future<Output = Result<S, E>>,
+{
+    type Output = Result<ConnectionService<S>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        match this.future.poll(cx) {
+            Poll::Ready(result) => {
+                let state = this
+                    .state
+                    .take()
+                    .expect("ConnectionFuture polled after completion");
+                Poll::Ready(result.map(|service| ConnectionService { service, state }))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Service handling the requests of a single connection, created by [`ConnectionMetrics`].
+#[derive(Debug, Clone)]
+pub struct ConnectionService<S> {
+    service: S,
+    state: Arc<ConnectionState>,
+}
+
+impl<S, B> Service<Request<B>> for ConnectionService<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let config = &self.state.config;
+        let version = version_label(request.version());
+        if self.state.requests.fetch_add(1, Ordering::Relaxed) == 0 {
+            let _ = self.state.version.set(version);
+            let name = config.name(Metric::ConnectionsOpen);
+            metrics::gauge!(name.clone(), version_labels(config, UNKNOWN_VERSION)).decrement(1);
+            metrics::gauge!(name, version_labels(config, version)).increment(1);
+        } else {
+            metrics::counter!(
+                config.name(Metric::ConnectionReusedRequests),
+                version_labels(config, version)
+            )
+            .increment(1);
+        }
+
+        self.service.call(request)
+    }
+}
+
+#[derive(Debug)]
+struct ConnectionState {
+    config: Arc<Config>,
+    accepted_at: Instant,
+    requests: AtomicU64,
+    version: OnceLock<&'static str>,
+}
+
+impl Drop for ConnectionState {
+    fn drop(&mut self) {
+        let config = &self.config;
+        let labels = version_labels(
+            config,
+            self.version.get().copied().unwrap_or(UNKNOWN_VERSION),
+        );
+
+        metrics::gauge!(config.name(Metric::ConnectionsOpen), labels.clone()).decrement(1);
+        metrics::histogram!(config.name(Metric::ConnectionDuration), labels.clone())
+            .record(self.accepted_at.elapsed().as_secs_f64());
+        metrics::histogram!(config.name(Metric::ConnectionRequests), labels)
+            .record(*self.requests.get_mut() as f64);
+    }
+}
+
+fn version_labels(config: &Config, version: &'static str) -> Vec<Label> {
+    let mut labels = config.constant_labels();
+    config.push_label(&mut labels, DefaultLabel::ProtocolVersion, version);
+    labels
+}
+
+pub(crate) fn version_label(version: Version) -> &'static str {
+    match version {
+        Version::HTTP_09 => "0.9",
+        Version::HTTP_10 => "1.0",
+        Version::HTTP_11 => "1.1",
+        Version::HTTP_2 => "2",
+        Version::HTTP_3 => "3",
+        _ => UNKNOWN_VERSION,
+    }
+}
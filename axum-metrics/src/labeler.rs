@@ -0,0 +1,135 @@
+use std::{any::Any, convert::Infallible, error::Error};
+
+use axum::{
+    http::{Request, Response},
+    response::{IntoResponseParts, ResponseParts},
+};
+use http_body_util::LengthLimitError;
+use metrics::{Label, SharedString};
+use tower::{load_shed::error::Overloaded, timeout::error::Elapsed};
+
+/// Adds labels derived from the request to every metric recorded for it.
+///
+/// Implemented for `()`, which adds nothing, and for closures taking the request and the labels
+/// to extend.
+pub trait RequestLabeler<B>: Send + Sync + 'static {
+    fn request_labels(&self, request: &Request<B>, labels: &mut Vec<Label>);
+}
+
+impl<B> RequestLabeler<B> for () {
+    fn request_labels(&self, _request: &Request<B>, _labels: &mut Vec<Label>) {}
+}
+
+impl<F, B> RequestLabeler<B> for F
+where
+    F: Fn(&Request<B>, &mut Vec<Label>) + Send + Sync + 'static,
+{
+    fn request_labels(&self, request: &Request<B>, labels: &mut Vec<Label>) {
+        self(request, labels)
+    }
+}
+
+/// Adds labels derived from the response to the metrics recorded once it is produced.
+///
+/// Implemented for `()`, which adds nothing, and for closures taking the response and the labels
+/// to extend.
+pub trait ResponseLabeler<B>: Send + Sync + 'static {
+    fn response_labels(&self, response: &Response<B>, labels: &mut Vec<Label>);
+}
+
+impl<B> ResponseLabeler<B> for () {
+    fn response_labels(&self, _response: &Response<B>, _labels: &mut Vec<Label>) {}
+}
+
+impl<F, B> ResponseLabeler<B> for F
+where
+    F: Fn(&Response<B>, &mut Vec<Label>) + Send + Sync + 'static,
+{
+    fn response_labels(&self, response: &Response<B>, labels: &mut Vec<Label>) {
+        self(response, labels)
+    }
+}
+
+/// Derives the `error_kind` label of errors returned by the inner service.
+///
+/// Implemented for `()` and for closures taking the error and returning its kind.
+///
+/// `()` looks through `BoxError`s and the sources of errors for the errors of the common tower
+/// and axum middleware: `timeout` for [`Elapsed`], `overloaded` for [`Overloaded`] and
+/// `body_limit` for [`LengthLimitError`]. Any other error is labelled `other`.
+pub trait ErrorKindClassifier<E>: Send + Sync + 'static {
+    fn error_kind(&self, error: &E) -> SharedString;
+}
+
+impl<E: 'static> ErrorKindClassifier<E> for () {
+    fn error_kind(&self, error: &E) -> SharedString {
+        let error = error as &dyn Any;
+        let error: &(dyn Error + 'static) =
+            if let Some(error) = error.downcast_ref::<Box<dyn Error + Send + Sync>>() {
+                error.as_ref()
+            } else if let Some(error) = error.downcast_ref::<axum::Error>() {
+                error
+            } else if let Some(error) = error.downcast_ref::<Elapsed>() {
+                error
+            } else if let Some(error) = error.downcast_ref::<Overloaded>() {
+                error
+            } else {
+                return SharedString::const_str("other");
+            };
+
+        let kind = std::iter::successors(Some(error), |&error| error.source()).find_map(|error| {
+            if error.is::<Elapsed>() {
+                Some("timeout")
+            } else if error.is::<Overloaded>() {
+                Some("overloaded")
+            } else if error.is::<LengthLimitError>() {
+                Some("body_limit")
+            } else {
+                None
+            }
+        });
+        SharedString::const_str(kind.unwrap_or("other"))
+    }
+}
+
+impl<F, E, K> ErrorKindClassifier<E> for F
+where
+    F: Fn(&E) -> K + Send + Sync + 'static,
+    K: Into<SharedString>,
+{
+    fn error_kind(&self, error: &E) -> SharedString {
+        self(error).into()
+    }
+}
+
+/// Labels set by a handler on its response, e.g. the tenant or whether the cache was hit.
+///
+/// Return it alongside the response, or insert it in the response extensions, to add its labels
+/// to the metrics of the request. Only keys allowed with
+/// [`MetricLayerBuilder::handler_labels`](crate::MetricLayerBuilder::handler_labels) are kept.
+#[derive(Debug, Clone, Default)]
+pub struct MetricLabels(pub(crate) Vec<Label>);
+
+impl MetricLabels {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, key: impl Into<SharedString>, value: impl Into<SharedString>) -> Self {
+        self.insert(key, value);
+        self
+    }
+
+    pub fn insert(&mut self, key: impl Into<SharedString>, value: impl Into<SharedString>) {
+        self.0.push(Label::new(key, value));
+    }
+}
+
+impl IntoResponseParts for MetricLabels {
+    type Error = Infallible;
+
+    fn into_response_parts(self, mut res: ResponseParts) -> Result<ResponseParts, Self::Error> {
+        res.extensions_mut().insert(self);
+        Ok(res)
+    }
+}
@@ -1,144 +1,465 @@
 use std::{
+    borrow::Cow,
     error::Error,
     future::Future,
     pin::Pin,
+    sync::Arc,
     task::{Context, Poll},
     time::Instant,
 };
 
+use axum::{
+    body::HttpBody,
+    http::{Request, Response},
+};
 use pin_project::{pin_project, pinned_drop};
 use tower::{Layer, Service};
 
-#[derive(Debug, Clone)]
-pub struct MetricLayer {
-    pub time_failures: bool,
+mod access_log;
+mod body;
+mod builder;
+mod connection;
+mod labeler;
+mod metadata;
+#[cfg(feature = "otlp")]
+mod otlp;
+#[cfg(feature = "prometheus")]
+mod prometheus;
+mod request_body;
+mod route_metrics;
+mod rules;
+#[cfg(feature = "testing")]
+mod testing;
+
+pub use access_log::{AccessLog, AccessLogFormat, RotatingFile};
+pub use body::InstrumentedBody;
+use body::{known_size, BodySize, BodyTiming};
+use builder::Config;
+pub use builder::{
+    Buckets, Conventions, DefaultLabel, Metric, MetricLayerBuilder, StatusGranularity,
+};
+pub use connection::{ConnectionFuture, ConnectionMetrics, ConnectionService};
+pub use labeler::{ErrorKindClassifier, MetricLabels, RequestLabeler, ResponseLabeler};
+use metadata::{route, Completion, RequestMetadata, ResponseMetadata};
+#[cfg(feature = "otlp")]
+pub use otlp::{OtlpError, OtlpExporter, OtlpHandle};
+#[cfg(feature = "prometheus")]
+pub use prometheus::PrometheusExporter;
+use request_body::StreamedRequestSize;
+pub use request_body::{RequestBodySizeLayer, RequestBodySizeService};
+use route_metrics::LayerConfig;
+pub use route_metrics::RouteMetrics;
+#[cfg(feature = "testing")]
+pub use testing::TestRecorder;
+
+pub const REQUESTS_DURATION_SECONDS: &str = "http_server_requests_duration_seconds";
+pub const REQUESTS_TOTAL: &str = "http_server_requests_total";
+pub const REQUESTS_IN_FLIGHT: &str = "http_server_requests_in_flight";
+pub const REQUESTS_SCHEDULE_DELAY_SECONDS: &str = "http_server_requests_schedule_delay_seconds";
+pub const RESPONSE_DURATION_SECONDS: &str = "http_server_response_duration_seconds";
+pub const RESPONSE_FIRST_BYTE_SECONDS: &str = "http_server_response_first_byte_seconds";
+pub const RESPONSE_BODY_DURATION_SECONDS: &str = "http_server_response_body_duration_seconds";
+pub const REQUEST_SIZE_BYTES: &str = "http_server_request_size_bytes";
+pub const RESPONSE_SIZE_BYTES: &str = "http_server_response_size_bytes";
+pub const ERRORS_TOTAL: &str = "http_server_errors_total";
+/// Histogram buckets used by the exporters unless overridden, in seconds.
+pub const DEFAULT_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+/// Histogram buckets of the body size metrics, in bytes.
+pub const DEFAULT_SIZE_BUCKETS: &[f64] = &[
+    64.0, 256.0, 1024.0, 4096.0, 16384.0, 65536.0, 262144.0, 1048576.0, 4194304.0, 16777216.0,
+];
+/// Histogram buckets of the number of requests served by a connection.
+pub const DEFAULT_COUNT_BUCKETS: &[f64] =
+    &[1.0, 2.0, 5.0, 10.0, 20.0, 50.0, 100.0, 200.0, 500.0, 1000.0];
+
+pub const CONNECTIONS_ACCEPTED_TOTAL: &str = "http_server_connections_accepted_total";
+pub const CONNECTIONS_OPEN: &str = "http_server_connections_open";
+pub const CONNECTION_DURATION_SECONDS: &str = "http_server_connection_duration_seconds";
+pub const CONNECTION_REQUESTS: &str = "http_server_connection_requests";
+pub const CONNECTION_REUSED_REQUESTS_TOTAL: &str = "http_server_connection_reused_requests_total";
+
+/// Buckets the exporters use for the histograms not measured in seconds, by the end of their
+/// names so that prefixed, client and OpenTelemetry names match too.
+#[cfg(any(feature = "otlp", feature = "prometheus"))]
+pub(crate) fn default_suffix_buckets() -> Vec<(String, Vec<f64>)> {
+    let sizes = [
+        "request_size_bytes",
+        "response_size_bytes",
+        "request.body.size",
+        "response.body.size",
+    ];
+    let counts = ["connection_requests", "connection.requests"];
+    sizes
+        .into_iter()
+        .map(|suffix| (suffix.to_string(), DEFAULT_SIZE_BUCKETS.to_vec()))
+        .chain(
+            counts
+                .into_iter()
+                .map(|suffix| (suffix.to_string(), DEFAULT_COUNT_BUCKETS.to_vec())),
+        )
+        .collect()
+}
+
+/// Layer recording metrics for the requests handled by the wrapped service.
+///
+/// Request bodies are passed to the inner service untouched, so the sizes of request bodies
+/// without a `Content-Length` are only recorded when a [`RequestBodySizeLayer`] is added inside
+/// of this layer. Response bodies are wrapped in an [`InstrumentedBody`].
+#[derive(Debug)]
+pub struct MetricLayer<RL = (), SL = (), EC = ()> {
+    config: Arc<Config>,
+    request_labeler: Arc<RL>,
+    response_labeler: Arc<SL>,
+    error_kind_classifier: Arc<EC>,
+}
+
+impl<RL, SL, EC> Clone for MetricLayer<RL, SL, EC> {
+    fn clone(&self) -> Self {
+        Self {
+            config: self.config.clone(),
+            request_labeler: self.request_labeler.clone(),
+            response_labeler: self.response_labeler.clone(),
+            error_kind_classifier: self.error_kind_classifier.clone(),
+        }
+    }
+}
+
+impl MetricLayer {
+    pub fn builder() -> MetricLayerBuilder {
+        MetricLayerBuilder::default()
+    }
+
+    /// Builder for a layer recording outgoing requests made through an HTTP client service.
+    ///
+    /// Metrics are named `http_client_*` and requests are labelled by `host` and `operation`
+    /// rather than `path`.
+    pub fn client_builder() -> MetricLayerBuilder {
+        MetricLayerBuilder::client()
+    }
+}
+
+impl<RL, SL, EC> MetricLayer<RL, SL, EC> {
+    /// Histogram buckets configured on the builder and the default ones of the histograms not
+    /// measured in seconds, by metric name.
+    pub fn histogram_buckets(&self) -> impl Iterator<Item = (&str, &[f64])> {
+        self.config
+            .buckets
+            .iter()
+            .map(|(name, buckets)| (name.as_ref(), buckets.as_slice()))
+    }
+
+    /// Histogram buckets of the duration series of single routes, as the metric name, the key
+    /// and value of the label identifying the route, and the buckets.
+    pub fn route_histogram_buckets(&self) -> impl Iterator<Item = (&str, &str, &str, &[f64])> {
+        let config = &self.config;
+        config
+            .label_key(DefaultLabel::Path)
+            .into_iter()
+            .flat_map(move |key| {
+                config
+                    .route_buckets
+                    .iter()
+                    .flat_map(move |(route, buckets)| {
+                        Metric::ALL
+                            .into_iter()
+                            .filter(Metric::is_duration)
+                            .map(move |metric| {
+                                (
+                                    config.name_str(metric),
+                                    key,
+                                    route.as_str(),
+                                    buckets.as_slice(),
+                                )
+                            })
+                    })
+            })
+    }
+}
+
+impl Default for MetricLayer {
+    fn default() -> Self {
+        Self::builder().build()
+    }
 }
 
-impl<S> Layer<S> for MetricLayer {
-    type Service = MetricService<S>;
+impl<S, RL, SL, EC> Layer<S> for MetricLayer<RL, SL, EC> {
+    type Service = MetricService<S, RL, SL, EC>;
 
     fn layer(&self, service: S) -> Self::Service {
         MetricService {
-            time_incomplete: self.time_failures,
+            config: self.config.clone(),
+            request_labeler: self.request_labeler.clone(),
+            response_labeler: self.response_labeler.clone(),
+            error_kind_classifier: self.error_kind_classifier.clone(),
             service,
         }
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct MetricService<S> {
-    time_incomplete: bool,
+#[derive(Debug)]
+pub struct MetricService<S, RL = (), SL = (), EC = ()> {
+    config: Arc<Config>,
+    request_labeler: Arc<RL>,
+    response_labeler: Arc<SL>,
+    error_kind_classifier: Arc<EC>,
     service: S,
 }
 
-struct RequestMetadata {
-    method: String,
-    path: String,
-}
-
-impl From<&axum::extract::Request> for RequestMetadata {
-    fn from(value: &axum::extract::Request) -> Self {
+impl<S, RL, SL, EC> Clone for MetricService<S, RL, SL, EC>
+where
+    S: Clone,
+{
+    fn clone(&self) -> Self {
         Self {
-            method: value.method().to_string(),
-            path: value.uri().path().to_string(),
+            config: self.config.clone(),
+            request_labeler: self.request_labeler.clone(),
+            response_labeler: self.response_labeler.clone(),
+            error_kind_classifier: self.error_kind_classifier.clone(),
+            service: self.service.clone(),
         }
     }
 }
 
-struct ResponseMetadata {
-    code: usize,
+/// Request extension overriding the `operation` label of a client request.
+#[derive(Debug, Clone)]
+pub struct Operation(pub Cow<'static, str>);
+
+/// How a request handled by [`MetricService`] ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// The inner service produced a response.
+    Completed,
+    /// The inner service returned an error.
+    Error,
+    /// The future was dropped before completing, e.g. on client disconnect or timeout.
+    Cancelled,
 }
 
-impl From<&axum::response::Response> for ResponseMetadata {
-    fn from(value: &axum::response::Response) -> Self {
-        Self {
-            code: value.status().as_u16() as usize,
+impl Outcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Outcome::Completed => "completed",
+            Outcome::Error => "error",
+            Outcome::Cancelled => "cancelled",
         }
     }
 }
 
-impl<S, Request> Service<Request> for MetricService<S>
+impl<S, ReqBody, ResBody, RL, SL, EC> Service<Request<ReqBody>> for MetricService<S, RL, SL, EC>
 where
-    S: Service<Request>,
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
     S::Future: Send + 'static,
     S::Error: Into<Box<dyn Error + Send + Sync>> + 'static,
-    S::Response: 'static,
-    RequestMetadata: for<'a> std::convert::From<&'a Request>,
-    ResponseMetadata: for<'a> std::convert::From<&'a S::Response>,
+    ReqBody: HttpBody,
+    ResBody: HttpBody,
+    RL: RequestLabeler<ReqBody>,
+    SL: ResponseLabeler<ResBody>,
+    EC: ErrorKindClassifier<S::Error>,
 {
-    type Response = S::Response;
+    type Response = Response<InstrumentedBody<ResBody>>;
     type Error = S::Error;
-    type Future = ObservedFuture<S::Future>;
+    type Future = ObservedFuture<S::Future, SL, EC>;
 
     fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
         self.service.poll_ready(cx)
     }
 
-    fn call(&mut self, request: Request) -> Self::Future {
-        let request_metadata = RequestMetadata::from(&request);
+    fn call(&mut self, mut request: Request<ReqBody>) -> Self::Future {
+        let started_at = Instant::now();
+        self.config.describe();
+        if !self
+            .config
+            .rules
+            .records(request.method(), &route(&request, &self.config))
+        {
+            return ObservedFuture {
+                response_future: self.service.call(request),
+                config: self.config.clone(),
+                response_labeler: self.response_labeler.clone(),
+                error_kind_classifier: self.error_kind_classifier.clone(),
+                started_at,
+                polled: false,
+                completion: None,
+                outcome: Outcome::Cancelled,
+                #[cfg(feature = "tracing")]
+                span: tracing::Span::none(),
+            };
+        }
+
+        let request_metadata =
+            RequestMetadata::new(&request, &self.config, self.request_labeler.as_ref());
+        let labels = request_metadata.labels(&self.config);
+        metrics::gauge!(self.config.name(Metric::RequestsInFlight), labels.clone()).increment(1);
+
+        if self.config.body_sizes && request_metadata.sampled() {
+            match known_size(request.headers(), request.body()) {
+                Some(bytes) => {
+                    metrics::histogram!(self.config.name(Metric::RequestSize), labels)
+                        .record(bytes as f64);
+                }
+                None => {
+                    request
+                        .extensions_mut()
+                        .insert(StreamedRequestSize(BodySize::new(
+                            &self.config,
+                            Metric::RequestSize,
+                            labels,
+                        )));
+                }
+            }
+        }
+        if !self.config.client {
+            request
+                .extensions_mut()
+                .insert(LayerConfig(self.config.clone()));
+        }
+        let completion = Completion::new(self.config.clone(), started_at, request_metadata);
+        #[cfg(feature = "tracing")]
+        let span = completion.span().clone();
+
+        #[cfg(feature = "tracing")]
+        let fut = span.in_scope(|| self.service.call(request));
+        #[cfg(not(feature = "tracing"))]
         let fut = self.service.call(request);
 
         ObservedFuture {
             response_future: fut,
-            time_failures: self.time_incomplete,
-            started_at: None,
-            request_metadata,
-            response_metadata: None,
+            config: self.config.clone(),
+            response_labeler: self.response_labeler.clone(),
+            error_kind_classifier: self.error_kind_classifier.clone(),
+            started_at,
+            polled: false,
+            completion: Some(completion),
+            outcome: Outcome::Cancelled,
+            #[cfg(feature = "tracing")]
+            span,
         }
     }
 }
 
 #[pin_project(PinnedDrop)]
-pub struct ObservedFuture<F> {
+pub struct ObservedFuture<F, SL = (), EC = ()> {
     #[pin]
     response_future: F,
-    time_failures: bool,
-    started_at: Option<Instant>,
-    request_metadata: RequestMetadata,
-    response_metadata: Option<ResponseMetadata>,
+    config: Arc<Config>,
+    response_labeler: Arc<SL>,
+    error_kind_classifier: Arc<EC>,
+    started_at: Instant,
+    polled: bool,
+    completion: Option<Completion>,
+    outcome: Outcome,
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
 }
 
 #[pinned_drop]
-impl<F> PinnedDrop for ObservedFuture<F> {
+impl<F, SL, EC> PinnedDrop for ObservedFuture<F, SL, EC> {
     fn drop(self: Pin<&mut Self>) {
         let this = self.project();
-        if let Some(started_at) = this.started_at {
-            println!("duration: {:#?}", started_at.elapsed());
-            if let Some(response_metadata) = this.response_metadata {
-                println!(
-                    "{}, {}, {}",
-                    this.request_metadata.method,
-                    this.request_metadata.path,
-                    response_metadata.code
-                );
-            } else {
-                println!(
-                    "{}, {}",
-                    this.request_metadata.method, this.request_metadata.path
-                );
-            }
+        if let Some(completion) = this.completion.take() {
+            completion.record(*this.outcome);
         }
     }
 }
 
-impl<F, Response, Error> Future for ObservedFuture<F>
+impl<F, B, SL, EC, Error> Future for ObservedFuture<F, SL, EC>
 where
-    F: Future<Output = Result<Response, Error>>,
-    ResponseMetadata: for<'a> std::convert::From<&'a Response>,
+    F: Future<Output = Result<Response<B>, Error>>,
+    B: HttpBody,
+    SL: ResponseLabeler<B>,
+    EC: ErrorKindClassifier<Error>,
 {
-    type Output = Result<Response, Error>;
+    type Output = Result<Response<InstrumentedBody<B>>, Error>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let this = self.project();
+        let config = this.config;
+        let Some(completion) = this.completion.as_mut() else {
+            // Requests excluded by the rules are passed through untouched.
+            return this.response_future.poll(cx).map(|result| {
+                result.map(|response| {
+                    response.map(|body| InstrumentedBody::new(body, None, None, None))
+                })
+            });
+        };
+        let sampled = completion.request_metadata().sampled();
 
-        if this.started_at.is_none() {
-            *this.started_at = Some(Instant::now());
+        if !*this.polled {
+            *this.polled = true;
+            if config.schedule_delay && sampled {
+                metrics::histogram!(
+                    config.name(Metric::RequestsScheduleDelay),
+                    completion.request_metadata().labels(config)
+                )
+                .record(this.started_at.elapsed().as_secs_f64());
+            }
         }
 
+        #[cfg(feature = "tracing")]
+        let _entered = this.span.enter();
+
         if let Poll::Ready(result) = this.response_future.poll(cx) {
-            if let Ok(response) = result.as_ref() {
-                *this.response_metadata = Some(ResponseMetadata::from(response));
-            }
+            let result = match result {
+                Ok(response) => {
+                    *this.outcome = Outcome::Completed;
+
+                    let response_metadata =
+                        ResponseMetadata::new(&response, config, this.response_labeler.as_ref());
+                    let mut labels = completion.request_metadata().labels(config);
+                    response_metadata.push_labels(config, &mut labels);
+
+                    let mut size = None;
+                    if config.body_sizes && sampled {
+                        match response_metadata.size() {
+                            Some(bytes) => {
+                                metrics::histogram!(
+                                    config.name(Metric::ResponseSize),
+                                    labels.clone()
+                                )
+                                .record(bytes as f64);
+                            }
+                            None => {
+                                size = Some(BodySize::new(
+                                    config,
+                                    Metric::ResponseSize,
+                                    labels.clone(),
+                                ));
+                            }
+                        }
+                    }
+                    let timing = (config.track_body && sampled)
+                        .then(|| BodyTiming::new(config.clone(), labels, *this.started_at));
+
+                    // Without a trailers-only response the gRPC status is only known once the
+                    // body has been streamed.
+                    let grpc_pending = config.grpc && !response_metadata.has_grpc_status();
+                    completion.set_response_metadata(response_metadata);
+                    let deferred = if grpc_pending {
+                        this.completion.take()
+                    } else {
+                        None
+                    };
+
+                    let response =
+                        response.map(|body| InstrumentedBody::new(body, timing, size, deferred));
+                    Ok(response)
+                }
+                Err(err) => {
+                    *this.outcome = Outcome::Error;
+
+                    let error_kind = this.error_kind_classifier.error_kind(&err);
+                    if let Some(name) = config.recorded_name(Metric::ErrorsTotal) {
+                        let mut labels = completion.request_metadata().labels(config);
+                        config.push_label(&mut labels, DefaultLabel::ErrorKind, error_kind.clone());
+                        metrics::counter!(name, labels).increment(1);
+                    }
+                    completion.set_error_kind(error_kind);
+
+                    Err(err)
+                }
+            };
             Poll::Ready(result)
         } else {
             Poll::Pending
@@ -0,0 +1,388 @@
+use std::{sync::Arc, time::Instant};
+
+use axum::{
+    body::HttpBody,
+    extract::MatchedPath,
+    http::{header::HOST, HeaderMap, Method, Request, Response, StatusCode},
+};
+use metrics::{Label, SharedString};
+
+use crate::{
+    access_log::AccessLogEntry, body::known_size, builder::Config, connection::version_label,
+    Conventions, DefaultLabel, Metric, MetricLabels, Operation, Outcome, RequestLabeler,
+    ResponseLabeler, StatusGranularity,
+};
+
+pub(crate) struct RequestMetadata {
+    method: String,
+    path: String,
+    version: &'static str,
+    scheme: Option<String>,
+    sampled: bool,
+    grpc: Option<GrpcMetadata>,
+    client: Option<ClientMetadata>,
+    access: Option<AccessLogEntry>,
+    extra_labels: Vec<Label>,
+}
+
+struct ClientMetadata {
+    host: String,
+    operation: Option<String>,
+}
+
+struct GrpcMetadata {
+    service: String,
+    method: String,
+}
+
+impl RequestMetadata {
+    pub(crate) fn new<B>(
+        request: &Request<B>,
+        config: &Config,
+        labeler: &impl RequestLabeler<B>,
+    ) -> Self {
+        let mut extra_labels = Vec::new();
+        labeler.request_labels(request, &mut extra_labels);
+
+        // gRPC calls are routed on `/package.Service/Method`.
+        let grpc = config.grpc.then(|| {
+            match request.uri().path().trim_start_matches('/').split_once('/') {
+                Some((service, method)) => GrpcMetadata {
+                    service: service.to_string(),
+                    method: method.to_string(),
+                },
+                None => GrpcMetadata {
+                    service: config.unmatched_path.clone(),
+                    method: config.unmatched_path.clone(),
+                },
+            }
+        });
+
+        let client = config.client.then(|| ClientMetadata {
+            host: request
+                .uri()
+                .host()
+                .or_else(|| {
+                    request
+                        .headers()
+                        .get(HOST)
+                        .and_then(|value| value.to_str().ok())
+                })
+                .unwrap_or_default()
+                .to_string(),
+            operation: request
+                .extensions()
+                .get::<Operation>()
+                .map(|operation| operation.0.to_string())
+                .or_else(|| config.operation.clone()),
+        });
+
+        let path = route(request, config);
+        Self {
+            method: method_label(request.method(), config),
+            version: version_label(request.version()),
+            scheme: request
+                .uri()
+                .scheme_str()
+                .map(str::to_string)
+                .or_else(|| config.scheme.clone()),
+            sampled: config.rules.sampled(&path),
+            path,
+            grpc,
+            client,
+            access: config
+                .access_log
+                .is_some()
+                .then(|| AccessLogEntry::new(request)),
+            extra_labels,
+        }
+    }
+
+    pub(crate) fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Whether the histograms of the request are recorded.
+    pub(crate) fn sampled(&self) -> bool {
+        self.sampled
+    }
+
+    pub(crate) fn access(&self) -> Option<&AccessLogEntry> {
+        self.access.as_ref()
+    }
+
+    pub(crate) fn labels(&self, config: &Config) -> Vec<Label> {
+        let mut labels = config.constant_labels();
+        match &self.grpc {
+            Some(grpc) => {
+                config.push_label(&mut labels, DefaultLabel::GrpcService, grpc.service.clone());
+                config.push_label(&mut labels, DefaultLabel::GrpcMethod, grpc.method.clone());
+            }
+            None => {
+                config.push_label(&mut labels, DefaultLabel::Method, self.method.clone());
+                // Client requests are not routed, and raw paths would explode cardinality.
+                if self.client.is_none() {
+                    config.push_label(&mut labels, DefaultLabel::Path, self.path.clone());
+                }
+            }
+        }
+        if config.conventions == Conventions::OpenTelemetry {
+            config.push_label(&mut labels, DefaultLabel::ProtocolVersion, self.version);
+            if let Some(scheme) = &self.scheme {
+                config.push_label(&mut labels, DefaultLabel::Scheme, scheme.clone());
+            }
+        }
+        if let Some(client) = &self.client {
+            config.push_label(&mut labels, DefaultLabel::Host, client.host.clone());
+            if let Some(operation) = &client.operation {
+                config.push_label(&mut labels, DefaultLabel::Operation, operation.clone());
+            }
+        }
+        labels.extend(self.extra_labels.iter().cloned());
+        labels
+    }
+}
+
+/// Method label of a request, with non-standard methods reported as `_OTHER` under the
+/// OpenTelemetry conventions.
+pub(crate) fn method_label(method: &Method, config: &Config) -> String {
+    let standard = [
+        Method::GET,
+        Method::HEAD,
+        Method::POST,
+        Method::PUT,
+        Method::DELETE,
+        Method::CONNECT,
+        Method::OPTIONS,
+        Method::TRACE,
+        Method::PATCH,
+    ];
+    if config.conventions == Conventions::OpenTelemetry && !standard.contains(method) {
+        "_OTHER".to_string()
+    } else {
+        method.to_string()
+    }
+}
+
+/// Route template the request matched, or the unmatched path label.
+pub(crate) fn route<B>(request: &Request<B>, config: &Config) -> String {
+    request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|matched_path| matched_path.as_str().to_string())
+        .unwrap_or_else(|| config.unmatched_path.clone())
+}
+
+pub(crate) struct ResponseMetadata {
+    status: StatusCode,
+    size: Option<u64>,
+    grpc_status: Option<String>,
+    extra_labels: Vec<Label>,
+}
+
+impl ResponseMetadata {
+    pub(crate) fn new<B>(
+        response: &Response<B>,
+        config: &Config,
+        labeler: &impl ResponseLabeler<B>,
+    ) -> Self
+    where
+        B: HttpBody,
+    {
+        let mut extra_labels = Vec::new();
+        labeler.response_labels(response, &mut extra_labels);
+        if let Some(handler_labels) = response.extensions().get::<MetricLabels>() {
+            extra_labels.extend(
+                handler_labels
+                    .0
+                    .iter()
+                    .filter(|label| config.handler_labels.contains(label.key()))
+                    .cloned(),
+            );
+        }
+
+        Self {
+            status: response.status(),
+            size: known_size(response.headers(), response.body()),
+            // Only set here for trailers-only responses, otherwise it arrives in the trailers.
+            grpc_status: grpc_status(response.headers()),
+            extra_labels,
+        }
+    }
+
+    pub(crate) fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Size of the response body, if known before it is streamed.
+    pub(crate) fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    pub(crate) fn has_grpc_status(&self) -> bool {
+        self.grpc_status.is_some()
+    }
+
+    pub(crate) fn push_labels(&self, config: &Config, labels: &mut Vec<Label>) {
+        let code = self.status.as_u16();
+        let class = || format!("{}xx", code / 100);
+        match config.status_granularity {
+            StatusGranularity::Code => {
+                config.push_label(labels, DefaultLabel::Status, code.to_string());
+            }
+            StatusGranularity::Class => {
+                config.push_label(labels, DefaultLabel::Status, class());
+            }
+            StatusGranularity::Both => {
+                config.push_label(labels, DefaultLabel::Status, code.to_string());
+                config.push_label(labels, DefaultLabel::StatusClass, class());
+            }
+        }
+        if let Some(classifier) = &config.error_classifier {
+            let error = if classifier.is_error(self.status) {
+                "true"
+            } else {
+                "false"
+            };
+            config.push_label(labels, DefaultLabel::Error, error);
+        }
+        labels.extend(self.extra_labels.iter().cloned());
+    }
+}
+
+pub(crate) fn grpc_status(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get("grpc-status")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Records the request level metrics once the request is over.
+///
+/// This normally happens when the response future completes, but gRPC responses hand it over to
+/// the body so the status can be read from the trailers.
+pub(crate) struct Completion {
+    config: Arc<Config>,
+    started_at: Instant,
+    request_metadata: RequestMetadata,
+    response_metadata: Option<ResponseMetadata>,
+    error_kind: Option<SharedString>,
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+impl Completion {
+    pub(crate) fn new(
+        config: Arc<Config>,
+        started_at: Instant,
+        request_metadata: RequestMetadata,
+    ) -> Self {
+        Self {
+            #[cfg(feature = "tracing")]
+            span: tracing::info_span!(
+                "request",
+                method = %request_metadata.method,
+                route = %request_metadata.path,
+            ),
+            config,
+            started_at,
+            request_metadata,
+            response_metadata: None,
+            error_kind: None,
+        }
+    }
+
+    pub(crate) fn request_metadata(&self) -> &RequestMetadata {
+        &self.request_metadata
+    }
+
+    #[cfg(feature = "tracing")]
+    pub(crate) fn span(&self) -> &tracing::Span {
+        &self.span
+    }
+
+    pub(crate) fn set_response_metadata(&mut self, response_metadata: ResponseMetadata) {
+        self.response_metadata = Some(response_metadata);
+    }
+
+    pub(crate) fn set_error_kind(&mut self, error_kind: SharedString) {
+        self.error_kind = Some(error_kind);
+    }
+
+    pub(crate) fn set_grpc_status(&mut self, grpc_status: String) {
+        if let Some(response_metadata) = &mut self.response_metadata {
+            response_metadata.grpc_status = Some(grpc_status);
+        }
+    }
+
+    pub(crate) fn record(self, outcome: Outcome) {
+        let config = &self.config;
+        let duration = self.started_at.elapsed();
+
+        let mut labels = self.request_metadata.labels(config);
+        metrics::gauge!(config.name(Metric::RequestsInFlight), labels.clone()).decrement(1);
+
+        if let Some(response_metadata) = &self.response_metadata {
+            response_metadata.push_labels(config, &mut labels);
+            if let Some(grpc_status) = &response_metadata.grpc_status {
+                config.push_label(&mut labels, DefaultLabel::GrpcStatus, grpc_status.clone());
+            }
+        }
+        config.push_label(&mut labels, DefaultLabel::Outcome, outcome.as_str());
+        if config.conventions == Conventions::OpenTelemetry {
+            if let Some(error_type) = self.error_type(outcome) {
+                config.push_label(&mut labels, DefaultLabel::ErrorKind, error_type);
+            }
+        }
+
+        #[cfg(feature = "tracing")]
+        {
+            let span = &self.span;
+            let response_metadata = self.response_metadata.as_ref();
+            tracing::info!(
+                parent: span,
+                status = response_metadata.map(|metadata| metadata.status.as_u16()),
+                grpc_status = response_metadata.and_then(|metadata| metadata.grpc_status.as_deref()),
+                outcome = outcome.as_str(),
+                latency_seconds = duration.as_secs_f64(),
+                "request completed"
+            );
+        }
+
+        if let Some(access_log) = &config.access_log {
+            access_log.log(
+                &self.request_metadata,
+                self.response_metadata.as_ref(),
+                outcome,
+                duration,
+            );
+        }
+
+        if let Some(name) = config.recorded_name(Metric::RequestsTotal) {
+            metrics::counter!(name, labels.clone()).increment(1);
+        }
+        if self.request_metadata.sampled && (outcome == Outcome::Completed || config.time_failures)
+        {
+            metrics::histogram!(config.name(Metric::RequestsDuration), labels)
+                .record(duration.as_secs_f64());
+        }
+    }
+
+    /// `error.type` of a failed request under the OpenTelemetry conventions.
+    fn error_type(&self, outcome: Outcome) -> Option<SharedString> {
+        match outcome {
+            Outcome::Completed => self
+                .response_metadata
+                .as_ref()
+                .filter(|metadata| metadata.status.is_server_error())
+                .map(|metadata| metadata.status.as_u16().to_string().into()),
+            // Body errors are not classified.
+            Outcome::Error => Some(
+                self.error_kind
+                    .clone()
+                    .unwrap_or(SharedString::const_str("_OTHER")),
+            ),
+            Outcome::Cancelled => Some(SharedString::const_str("cancelled")),
+        }
+    }
+}
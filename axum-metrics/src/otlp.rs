@@ -0,0 +1,715 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::http::{header::CONTENT_TYPE, uri::InvalidUri, Request, StatusCode, Uri};
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper_util::{
+    client::legacy::{connect::HttpConnector, Client},
+    rt::TokioExecutor,
+};
+use metrics::{
+    Counter, Gauge, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder, SharedString, Unit,
+};
+use prost::Message;
+use tokio::{sync::oneshot, task::JoinHandle};
+
+use crate::{default_suffix_buckets, MetricLayer, DEFAULT_BUCKETS};
+
+/// Installs a global recorder pushing metrics to an OpenTelemetry collector over OTLP/HTTP.
+///
+/// Metrics are aggregated in memory and exported with cumulative temporality every interval,
+/// encoded as protobuf. Only plain HTTP endpoints are supported, e.g. a collector sidecar.
+#[derive(Debug, Clone)]
+pub struct OtlpExporter {
+    endpoint: String,
+    interval: Duration,
+    timeout: Duration,
+    resource: Vec<(String, String)>,
+    buckets: Vec<f64>,
+    metric_buckets: Vec<(String, Vec<f64>)>,
+    suffix_buckets: Vec<(String, Vec<f64>)>,
+    route_buckets: Vec<RouteBuckets>,
+}
+
+impl Default for OtlpExporter {
+    fn default() -> Self {
+        Self {
+            endpoint: "http://localhost:4318/v1/metrics".to_string(),
+            interval: Duration::from_secs(60),
+            timeout: Duration::from_secs(10),
+            resource: Vec::new(),
+            buckets: DEFAULT_BUCKETS.to_vec(),
+            metric_buckets: Vec::new(),
+            suffix_buckets: default_suffix_buckets(),
+            route_buckets: Vec::new(),
+        }
+    }
+}
+
+impl OtlpExporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// URL metrics are posted to, `http://localhost:4318/v1/metrics` by default.
+    pub fn endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.endpoint = endpoint.into();
+        self
+    }
+
+    /// How often metrics are exported, every 60 seconds by default.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// How long an export may take before it is abandoned, 10 seconds by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Adds an attribute describing the exporting process, e.g. `service.name`.
+    pub fn resource_attribute(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.resource.push((key.into(), value.into()));
+        self
+    }
+
+    /// Bucket boundaries used for every histogram without its own, [`DEFAULT_BUCKETS`] by
+    /// default.
+    ///
+    /// Histograms named like the body size and connection request histograms, i.e. ending in
+    /// `request_size_bytes`, `request.body.size`, `connection_requests`, … whatever the prefix,
+    /// have their own buckets.
+    pub fn buckets(mut self, buckets: &[f64]) -> Self {
+        self.buckets = buckets.to_vec();
+        self
+    }
+
+    /// Bucket boundaries used for the histogram named `name`, over those matched by its suffix.
+    pub fn metric_buckets(mut self, name: impl Into<String>, buckets: &[f64]) -> Self {
+        self.metric_buckets.push((name.into(), buckets.to_vec()));
+        self
+    }
+
+    /// Uses the histogram buckets configured on a [`MetricLayer`], including those of single
+    /// routes.
+    pub fn layer_buckets<RL, SL, EC>(mut self, layer: &MetricLayer<RL, SL, EC>) -> Self {
+        self.metric_buckets.extend(
+            layer
+                .histogram_buckets()
+                .map(|(name, buckets)| (name.to_string(), buckets.to_vec())),
+        );
+        self.route_buckets
+            .extend(
+                layer
+                    .route_histogram_buckets()
+                    .map(|(name, key, route, buckets)| RouteBuckets {
+                        name: name.to_string(),
+                        key: key.to_string(),
+                        route: route.to_string(),
+                        bounds: buckets.to_vec(),
+                    }),
+            );
+        self
+    }
+
+    /// Installs the recorder globally and starts exporting on the current Tokio runtime.
+    ///
+    /// Call [`OtlpHandle::shutdown`] before exiting so the last interval is not lost. Dropping
+    /// the handle instead leaves the export running in the background.
+    pub fn install(self) -> Result<OtlpHandle, OtlpError> {
+        let endpoint = self.endpoint.parse::<Uri>()?;
+        // Checked before setting the recorder, which cannot be undone.
+        let runtime = tokio::runtime::Handle::try_current().map_err(|_| OtlpError::NoRuntime)?;
+
+        let storage = Arc::new(Storage {
+            buckets: self.buckets,
+            metric_buckets: self.metric_buckets.into_iter().collect(),
+            suffix_buckets: self.suffix_buckets,
+            route_buckets: self.route_buckets,
+            ..Storage::default()
+        });
+        metrics::set_global_recorder(OtlpRecorder(storage.clone()))
+            .map_err(|_| OtlpError::AlreadyInstalled)?;
+
+        let export = Arc::new(Export {
+            storage,
+            client: Client::builder(TokioExecutor::new()).build_http(),
+            endpoint,
+            timeout: self.timeout,
+            resource: self
+                .resource
+                .into_iter()
+                .map(|(key, value)| proto::KeyValue::new(key, value))
+                .collect(),
+            started_at: unix_nanos(SystemTime::now()),
+        });
+
+        let (shutdown, mut shutdown_rx) = oneshot::channel();
+        let task = runtime.spawn({
+            let export = export.clone();
+            let mut interval = tokio::time::interval(self.interval);
+            async move {
+                // The first tick completes immediately.
+                interval.tick().await;
+                let mut detached = false;
+                loop {
+                    tokio::select! {
+                        _ = interval.tick() => {
+                            // Failed exports are retried with cumulative values on the next tick.
+                            let _ = export.run().await;
+                        }
+                        // A dropped handle closes the channel without asking to stop.
+                        result = &mut shutdown_rx, if !detached => match result {
+                            Ok(()) => break,
+                            Err(_) => detached = true,
+                        },
+                    }
+                }
+            }
+        });
+
+        Ok(OtlpHandle {
+            export,
+            shutdown,
+            task,
+        })
+    }
+}
+
+/// Handle to the exporter started by [`OtlpExporter::install`].
+#[must_use = "call `shutdown` before exiting to export the last interval"]
+pub struct OtlpHandle {
+    export: Arc<Export>,
+    shutdown: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl OtlpHandle {
+    /// Exports the current metrics right away.
+    pub async fn flush(&self) -> Result<(), OtlpError> {
+        self.export.run().await
+    }
+
+    /// Stops the periodic export and flushes the metrics recorded since the last one.
+    pub async fn shutdown(self) -> Result<(), OtlpError> {
+        let _ = self.shutdown.send(());
+        let _ = self.task.await;
+        self.export.run().await
+    }
+}
+
+impl fmt::Debug for OtlpHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OtlpHandle")
+            .field("endpoint", &self.export.endpoint)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Error installing the OTLP exporter or exporting metrics.
+#[derive(Debug)]
+pub enum OtlpError {
+    /// The endpoint is not a valid URI.
+    InvalidEndpoint(InvalidUri),
+    /// A global recorder was already installed.
+    AlreadyInstalled,
+    /// `install` was called outside of a Tokio runtime.
+    NoRuntime,
+    /// The collector could not be reached.
+    Request(hyper_util::client::legacy::Error),
+    /// The collector rejected the export.
+    Status(StatusCode),
+    /// The collector did not respond within the timeout.
+    Timeout,
+}
+
+impl fmt::Display for OtlpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OtlpError::InvalidEndpoint(err) => write!(f, "invalid OTLP endpoint: {err}"),
+            OtlpError::AlreadyInstalled => f.write_str("a global metrics recorder is already set"),
+            OtlpError::Request(err) => write!(f, "failed to export metrics: {err}"),
+            OtlpError::NoRuntime => f.write_str("the OTLP exporter requires a Tokio runtime"),
+            OtlpError::Status(status) => write!(f, "collector responded with {status}"),
+            OtlpError::Timeout => f.write_str("timed out exporting metrics"),
+        }
+    }
+}
+
+impl std::error::Error for OtlpError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            OtlpError::InvalidEndpoint(err) => Some(err),
+            OtlpError::Request(err) => Some(err),
+            OtlpError::AlreadyInstalled
+            | OtlpError::NoRuntime
+            | OtlpError::Status(_)
+            | OtlpError::Timeout => None,
+        }
+    }
+}
+
+impl From<InvalidUri> for OtlpError {
+    fn from(err: InvalidUri) -> Self {
+        OtlpError::InvalidEndpoint(err)
+    }
+}
+
+struct Export {
+    storage: Arc<Storage>,
+    client: Client<HttpConnector, Full<Bytes>>,
+    endpoint: Uri,
+    timeout: Duration,
+    resource: Vec<proto::KeyValue>,
+    started_at: u64,
+}
+
+impl Export {
+    async fn run(&self) -> Result<(), OtlpError> {
+        let request = self.storage.collect(
+            self.resource.clone(),
+            self.started_at,
+            unix_nanos(SystemTime::now()),
+        );
+        let request = Request::post(self.endpoint.clone())
+            .header(CONTENT_TYPE, "application/x-protobuf")
+            .body(Full::new(Bytes::from(request.encode_to_vec())))
+            .expect("OTLP request is valid");
+
+        let response = tokio::time::timeout(self.timeout, self.client.request(request))
+            .await
+            .map_err(|_| OtlpError::Timeout)?
+            .map_err(OtlpError::Request)?;
+        if !response.status().is_success() {
+            return Err(OtlpError::Status(response.status()));
+        }
+        Ok(())
+    }
+}
+
+struct OtlpRecorder(Arc<Storage>);
+
+#[derive(Default)]
+struct Storage {
+    buckets: Vec<f64>,
+    metric_buckets: HashMap<String, Vec<f64>>,
+    suffix_buckets: Vec<(String, Vec<f64>)>,
+    route_buckets: Vec<RouteBuckets>,
+    descriptions: Mutex<HashMap<String, Description>>,
+    counters: Mutex<HashMap<Key, Arc<AtomicU64>>>,
+    gauges: Mutex<HashMap<Key, Arc<AtomicU64>>>,
+    histograms: Mutex<HashMap<Key, Arc<Buckets>>>,
+}
+
+/// Bucket boundaries of the series of a metric carrying a route label.
+#[derive(Debug, Clone)]
+struct RouteBuckets {
+    name: String,
+    key: String,
+    route: String,
+    bounds: Vec<f64>,
+}
+
+/// Unit and description of a metric, set through the `describe_*` macros.
+struct Description {
+    unit: Option<Unit>,
+    description: SharedString,
+}
+
+/// Cumulative bucket counts of a histogram.
+struct Buckets {
+    bounds: Vec<f64>,
+    state: Mutex<BucketsState>,
+}
+
+struct BucketsState {
+    counts: Vec<u64>,
+    count: u64,
+    sum: f64,
+}
+
+impl HistogramFn for Buckets {
+    fn record(&self, value: f64) {
+        // Bucket `i` holds the values in `(bounds[i - 1], bounds[i]]`.
+        let index = self.bounds.partition_point(|bound| *bound < value);
+        let mut state = self.state.lock().unwrap();
+        state.counts[index] += 1;
+        state.count += 1;
+        state.sum += value;
+    }
+}
+
+impl Storage {
+    fn collect(
+        &self,
+        resource: Vec<proto::KeyValue>,
+        started_at: u64,
+        now: u64,
+    ) -> proto::ExportMetricsServiceRequest {
+        // Series are grouped into one metric per name.
+        let mut metrics = BTreeMap::<String, proto::Data>::new();
+
+        for (key, counter) in self.counters.lock().unwrap().iter() {
+            let point = proto::NumberDataPoint {
+                attributes: attributes(key),
+                start_time_unix_nano: started_at,
+                time_unix_nano: now,
+                value: Some(proto::Value::AsInt(counter.load(Ordering::Relaxed) as i64)),
+            };
+            match metrics.entry(key.name().to_string()).or_insert_with(|| {
+                proto::Data::Sum(proto::Sum {
+                    data_points: Vec::new(),
+                    aggregation_temporality: proto::AGGREGATION_TEMPORALITY_CUMULATIVE,
+                    is_monotonic: true,
+                })
+            }) {
+                proto::Data::Sum(sum) => sum.data_points.push(point),
+                _ => continue,
+            }
+        }
+
+        for (key, gauge) in self.gauges.lock().unwrap().iter() {
+            let point = proto::NumberDataPoint {
+                attributes: attributes(key),
+                start_time_unix_nano: started_at,
+                time_unix_nano: now,
+                value: Some(proto::Value::AsDouble(f64::from_bits(
+                    gauge.load(Ordering::Relaxed),
+                ))),
+            };
+            match metrics
+                .entry(key.name().to_string())
+                .or_insert_with(|| proto::Data::Gauge(proto::Gauge::default()))
+            {
+                proto::Data::Gauge(gauge) => gauge.data_points.push(point),
+                _ => continue,
+            }
+        }
+
+        for (key, buckets) in self.histograms.lock().unwrap().iter() {
+            let state = buckets.state.lock().unwrap();
+            let point = proto::HistogramDataPoint {
+                attributes: attributes(key),
+                start_time_unix_nano: started_at,
+                time_unix_nano: now,
+                count: state.count,
+                sum: Some(state.sum),
+                bucket_counts: state.counts.clone(),
+                explicit_bounds: buckets.bounds.clone(),
+            };
+            match metrics.entry(key.name().to_string()).or_insert_with(|| {
+                proto::Data::Histogram(proto::Histogram {
+                    data_points: Vec::new(),
+                    aggregation_temporality: proto::AGGREGATION_TEMPORALITY_CUMULATIVE,
+                })
+            }) {
+                proto::Data::Histogram(histogram) => histogram.data_points.push(point),
+                _ => continue,
+            }
+        }
+
+        let descriptions = self.descriptions.lock().unwrap();
+        proto::ExportMetricsServiceRequest {
+            resource_metrics: vec![proto::ResourceMetrics {
+                resource: Some(proto::Resource {
+                    attributes: resource,
+                }),
+                scope_metrics: vec![proto::ScopeMetrics {
+                    scope: Some(proto::InstrumentationScope {
+                        name: env!("CARGO_PKG_NAME").to_string(),
+                        version: env!("CARGO_PKG_VERSION").to_string(),
+                    }),
+                    metrics: metrics
+                        .into_iter()
+                        .map(|(name, data)| {
+                            let (description, unit) = match descriptions.get(&name) {
+                                Some(described) => (
+                                    described.description.to_string(),
+                                    described.unit.map(unit_symbol).unwrap_or_default(),
+                                ),
+                                None => Default::default(),
+                            };
+                            proto::Metric {
+                                name,
+                                description,
+                                unit: unit.to_string(),
+                                data: Some(data),
+                            }
+                        })
+                        .collect(),
+                }],
+            }],
+        }
+    }
+}
+
+fn attributes(key: &Key) -> Vec<proto::KeyValue> {
+    key.labels()
+        .map(|label| proto::KeyValue::new(label.key(), label.value()))
+        .collect()
+}
+
+/// UCUM symbol of a unit, as expected by OpenTelemetry.
+fn unit_symbol(unit: Unit) -> &'static str {
+    match unit {
+        Unit::Count => "1",
+        Unit::Percent => "%",
+        Unit::Seconds => "s",
+        Unit::Milliseconds => "ms",
+        Unit::Microseconds => "us",
+        Unit::Nanoseconds => "ns",
+        Unit::Tebibytes => "TiBy",
+        Unit::Gigibytes => "GiBy",
+        Unit::Mebibytes => "MiBy",
+        Unit::Kibibytes => "KiBy",
+        Unit::Bytes => "By",
+        Unit::TerabitsPerSecond => "Tbit/s",
+        Unit::GigabitsPerSecond => "Gbit/s",
+        Unit::MegabitsPerSecond => "Mbit/s",
+        Unit::KilobitsPerSecond => "kbit/s",
+        Unit::BitsPerSecond => "bit/s",
+        Unit::CountPerSecond => "1/s",
+    }
+}
+
+fn unix_nanos(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64
+}
+
+impl OtlpRecorder {
+    fn describe(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+        self.0
+            .descriptions
+            .lock()
+            .unwrap()
+            .insert(key.as_str().to_string(), Description { unit, description });
+    }
+}
+
+impl Recorder for OtlpRecorder {
+    fn describe_counter(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+        self.describe(key, unit, description);
+    }
+
+    fn describe_gauge(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+        self.describe(key, unit, description);
+    }
+
+    fn describe_histogram(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+        self.describe(key, unit, description);
+    }
+
+    fn register_counter(&self, key: &Key, _metadata: &Metadata<'_>) -> Counter {
+        let mut counters = self.0.counters.lock().unwrap();
+        Counter::from_arc(counters.entry(key.clone()).or_default().clone())
+    }
+
+    fn register_gauge(&self, key: &Key, _metadata: &Metadata<'_>) -> Gauge {
+        let mut gauges = self.0.gauges.lock().unwrap();
+        Gauge::from_arc(gauges.entry(key.clone()).or_default().clone())
+    }
+
+    fn register_histogram(&self, key: &Key, _metadata: &Metadata<'_>) -> Histogram {
+        let route_bounds = self.0.route_buckets.iter().find(|route_buckets| {
+            route_buckets.name == key.name()
+                && key.labels().any(|label| {
+                    label.key() == route_buckets.key && label.value() == route_buckets.route
+                })
+        });
+        let bounds = match route_bounds {
+            Some(route_buckets) => &route_buckets.bounds,
+            None => self
+                .0
+                .metric_buckets
+                .get(key.name())
+                .or_else(|| {
+                    self.0
+                        .suffix_buckets
+                        .iter()
+                        .find(|(suffix, _)| key.name().ends_with(suffix.as_str()))
+                        .map(|(_, bounds)| bounds)
+                })
+                .unwrap_or(&self.0.buckets),
+        };
+        let mut histograms = self.0.histograms.lock().unwrap();
+        let buckets = histograms.entry(key.clone()).or_insert_with(|| {
+            Arc::new(Buckets {
+                bounds: bounds.clone(),
+                state: Mutex::new(BucketsState {
+                    counts: vec![0; bounds.len() + 1],
+                    count: 0,
+                    sum: 0.0,
+                }),
+            })
+        });
+        Histogram::from_arc(buckets.clone())
+    }
+}
+
+/// The subset of the OTLP metrics protocol needed to export counters, gauges and histograms.
+mod proto {
+    pub(super) const AGGREGATION_TEMPORALITY_CUMULATIVE: i32 = 2;
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub(super) struct ExportMetricsServiceRequest {
+        #[prost(message, repeated, tag = "1")]
+        pub(super) resource_metrics: Vec<ResourceMetrics>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub(super) struct ResourceMetrics {
+        #[prost(message, optional, tag = "1")]
+        pub(super) resource: Option<Resource>,
+        #[prost(message, repeated, tag = "2")]
+        pub(super) scope_metrics: Vec<ScopeMetrics>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub(super) struct Resource {
+        #[prost(message, repeated, tag = "1")]
+        pub(super) attributes: Vec<KeyValue>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub(super) struct ScopeMetrics {
+        #[prost(message, optional, tag = "1")]
+        pub(super) scope: Option<InstrumentationScope>,
+        #[prost(message, repeated, tag = "2")]
+        pub(super) metrics: Vec<Metric>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub(super) struct InstrumentationScope {
+        #[prost(string, tag = "1")]
+        pub(super) name: String,
+        #[prost(string, tag = "2")]
+        pub(super) version: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub(super) struct Metric {
+        #[prost(string, tag = "1")]
+        pub(super) name: String,
+        #[prost(string, tag = "2")]
+        pub(super) description: String,
+        #[prost(string, tag = "3")]
+        pub(super) unit: String,
+        #[prost(oneof = "Data", tags = "5, 7, 9")]
+        pub(super) data: Option<Data>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub(super) enum Data {
+        #[prost(message, tag = "5")]
+        Gauge(Gauge),
+        #[prost(message, tag = "7")]
+        Sum(Sum),
+        #[prost(message, tag = "9")]
+        Histogram(Histogram),
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub(super) struct Gauge {
+        #[prost(message, repeated, tag = "1")]
+        pub(super) data_points: Vec<NumberDataPoint>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub(super) struct Sum {
+        #[prost(message, repeated, tag = "1")]
+        pub(super) data_points: Vec<NumberDataPoint>,
+        #[prost(int32, tag = "2")]
+        pub(super) aggregation_temporality: i32,
+        #[prost(bool, tag = "3")]
+        pub(super) is_monotonic: bool,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub(super) struct Histogram {
+        #[prost(message, repeated, tag = "1")]
+        pub(super) data_points: Vec<HistogramDataPoint>,
+        #[prost(int32, tag = "2")]
+        pub(super) aggregation_temporality: i32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub(super) struct NumberDataPoint {
+        #[prost(message, repeated, tag = "7")]
+        pub(super) attributes: Vec<KeyValue>,
+        #[prost(fixed64, tag = "2")]
+        pub(super) start_time_unix_nano: u64,
+        #[prost(fixed64, tag = "3")]
+        pub(super) time_unix_nano: u64,
+        #[prost(oneof = "Value", tags = "4, 6")]
+        pub(super) value: Option<Value>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub(super) enum Value {
+        #[prost(double, tag = "4")]
+        AsDouble(f64),
+        #[prost(sfixed64, tag = "6")]
+        AsInt(i64),
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub(super) struct HistogramDataPoint {
+        #[prost(message, repeated, tag = "9")]
+        pub(super) attributes: Vec<KeyValue>,
+        #[prost(fixed64, tag = "2")]
+        pub(super) start_time_unix_nano: u64,
+        #[prost(fixed64, tag = "3")]
+        pub(super) time_unix_nano: u64,
+        #[prost(fixed64, tag = "4")]
+        pub(super) count: u64,
+        #[prost(double, optional, tag = "5")]
+        pub(super) sum: Option<f64>,
+        #[prost(fixed64, repeated, tag = "6")]
+        pub(super) bucket_counts: Vec<u64>,
+        #[prost(double, repeated, tag = "7")]
+        pub(super) explicit_bounds: Vec<f64>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub(super) struct KeyValue {
+        #[prost(string, tag = "1")]
+        pub(super) key: String,
+        #[prost(message, optional, tag = "2")]
+        pub(super) value: Option<AnyValue>,
+    }
+
+    impl KeyValue {
+        pub(super) fn new(key: impl Into<String>, value: impl Into<String>) -> Self {
+            Self {
+                key: key.into(),
+                value: Some(AnyValue {
+                    value: Some(AnyValueKind::StringValue(value.into())),
+                }),
+            }
+        }
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub(super) struct AnyValue {
+        #[prost(oneof = "AnyValueKind", tags = "1")]
+        pub(super) value: Option<AnyValueKind>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub(super) enum AnyValueKind {
+        #[prost(string, tag = "1")]
+        StringValue(String),
+    }
+}
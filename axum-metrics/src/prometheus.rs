@@ -0,0 +1,90 @@
+use axum::{http::header::CONTENT_TYPE, routing::get, Router};
+use metrics_exporter_prometheus::{BuildError, Matcher, PrometheusBuilder};
+
+use crate::{default_suffix_buckets, MetricLayer, DEFAULT_BUCKETS};
+
+/// Installs a global Prometheus recorder and serves its text exposition format.
+#[derive(Debug, Clone)]
+pub struct PrometheusExporter {
+    path: String,
+    buckets: Vec<f64>,
+    metric_buckets: Vec<(String, Vec<f64>)>,
+    suffix_buckets: Vec<(String, Vec<f64>)>,
+}
+
+impl Default for PrometheusExporter {
+    fn default() -> Self {
+        Self {
+            path: "/metrics".to_string(),
+            buckets: DEFAULT_BUCKETS.to_vec(),
+            metric_buckets: Vec::new(),
+            suffix_buckets: default_suffix_buckets(),
+        }
+    }
+}
+
+impl PrometheusExporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Path the returned router serves the metrics on, `/metrics` by default.
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.path = path.into();
+        self
+    }
+
+    /// Bucket boundaries used for every histogram without its own, [`DEFAULT_BUCKETS`] by
+    /// default.
+    ///
+    /// Histograms named like the body size and connection request histograms, i.e. ending in
+    /// `request_size_bytes`, `request.body.size`, `connection_requests`, … whatever the prefix,
+    /// have their own buckets.
+    pub fn buckets(mut self, buckets: &[f64]) -> Self {
+        self.buckets = buckets.to_vec();
+        self
+    }
+
+    /// Bucket boundaries used for the histogram named `name`, over those matched by its suffix.
+    pub fn metric_buckets(mut self, name: impl Into<String>, buckets: &[f64]) -> Self {
+        self.metric_buckets.push((name.into(), buckets.to_vec()));
+        self
+    }
+
+    /// Uses the histogram buckets configured on a [`MetricLayer`].
+    ///
+    /// Buckets of single routes are ignored, as Prometheus buckets every series of a metric the
+    /// same way.
+    pub fn layer_buckets<RL, SL, EC>(mut self, layer: &MetricLayer<RL, SL, EC>) -> Self {
+        self.metric_buckets.extend(
+            layer
+                .histogram_buckets()
+                .map(|(name, buckets)| (name.to_string(), buckets.to_vec())),
+        );
+        self
+    }
+
+    /// Installs the recorder globally and returns a router serving the metrics.
+    pub fn install<S>(self) -> Result<Router<S>, BuildError>
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        let mut builder = PrometheusBuilder::new().set_buckets(&self.buckets)?;
+        // Full names take precedence over suffixes.
+        for (name, buckets) in &self.metric_buckets {
+            builder = builder.set_buckets_for_metric(Matcher::Full(name.clone()), buckets)?;
+        }
+        for (suffix, buckets) in &self.suffix_buckets {
+            builder = builder.set_buckets_for_metric(Matcher::Suffix(suffix.clone()), buckets)?;
+        }
+        let handle = builder.install_recorder()?;
+
+        Ok(Router::new().route(
+            &self.path,
+            get(move || {
+                let body = handle.render();
+                async move { ([(CONTENT_TYPE, "text/plain; version=0.0.4")], body) }
+            }),
+        ))
+    }
+}
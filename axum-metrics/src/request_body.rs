@@ -0,0 +1,69 @@
+use std::task::{Context, Poll};
+
+use axum::{body::HttpBody, http::Request};
+use tower::{Layer, Service};
+
+use crate::{body::BodySize, InstrumentedBody};
+
+/// Request extension left by [`MetricService`](crate::MetricService) for request bodies whose size
+/// is only known once streamed.
+#[derive(Clone)]
+pub(crate) struct StreamedRequestSize(pub(crate) BodySize);
+
+/// Layer counting the bytes of request bodies without a `Content-Length`, for the
+/// [`MetricLayer`](crate::MetricLayer) enclosing it.
+///
+/// The request bodies are wrapped in an [`InstrumentedBody`], so the inner service has to accept
+/// them. Requests the enclosing layer does not sample, or whose size it already knows, are only
+/// rewrapped.
+///
+/// ```ignore
+/// let app = Router::new()
+///     .route("/upload", post(upload))
+///     .layer(RequestBodySizeLayer::new())
+///     .layer(MetricLayer::default());
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RequestBodySizeLayer;
+
+impl RequestBodySizeLayer {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<S> Layer<S> for RequestBodySizeLayer {
+    type Service = RequestBodySizeService<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        RequestBodySizeService { service }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RequestBodySizeService<S> {
+    service: S,
+}
+
+impl<S, B> Service<Request<B>> for RequestBodySizeService<S>
+where
+    S: Service<Request<InstrumentedBody<B>>>,
+    B: HttpBody,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<B>) -> Self::Future {
+        let size = request
+            .extensions_mut()
+            .remove::<StreamedRequestSize>()
+            .map(|size| size.0);
+        self.service
+            .call(request.map(|body| InstrumentedBody::new(body, None, size, None)))
+    }
+}
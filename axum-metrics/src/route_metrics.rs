@@ -0,0 +1,84 @@
+use std::{convert::Infallible, sync::Arc};
+
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, MatchedPath},
+    http::request::Parts,
+};
+use metrics::{Counter, Gauge, Histogram, Label, SharedString};
+
+use crate::{builder::Config, metadata::method_label, DefaultLabel};
+
+/// Request extension through which [`RouteMetrics`] finds the config of the enclosing layer.
+#[derive(Clone)]
+pub(crate) struct LayerConfig(pub(crate) Arc<Config>);
+
+/// Extractor handing out metrics labelled like the series of the [`MetricLayer`](crate::MetricLayer)
+/// handling the request.
+///
+/// Metrics carry the layer's constant labels followed by the `method` and `path` of the request,
+/// so business metrics recorded in handlers can be joined with the request metrics. Without an
+/// enclosing layer only `method` and `path` are set.
+#[derive(Debug, Clone)]
+pub struct RouteMetrics {
+    labels: Vec<Label>,
+}
+
+impl RouteMetrics {
+    /// Returns a copy with an extra label attached to every metric it hands out.
+    pub fn with_label(&self, key: impl Into<SharedString>, value: impl Into<SharedString>) -> Self {
+        let mut labels = self.labels.clone();
+        labels.push(Label::new(key, value));
+        Self { labels }
+    }
+
+    pub fn labels(&self) -> &[Label] {
+        &self.labels
+    }
+
+    pub fn counter(&self, name: impl Into<SharedString>) -> Counter {
+        metrics::counter!(name.into(), self.labels.clone())
+    }
+
+    pub fn gauge(&self, name: impl Into<SharedString>) -> Gauge {
+        metrics::gauge!(name.into(), self.labels.clone())
+    }
+
+    pub fn histogram(&self, name: impl Into<SharedString>) -> Histogram {
+        metrics::histogram!(name.into(), self.labels.clone())
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for RouteMetrics
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let config = parts
+            .extensions
+            .get::<LayerConfig>()
+            .map(|layer_config| layer_config.0.clone())
+            .unwrap_or_default();
+
+        // The route is only known once the request has been routed, which may be after the
+        // layer saw it.
+        let path = parts
+            .extensions
+            .get::<MatchedPath>()
+            .map(|matched_path| matched_path.as_str().to_string())
+            .unwrap_or_else(|| config.unmatched_path.clone());
+
+        let mut labels = config.constant_labels();
+        config.push_label(
+            &mut labels,
+            DefaultLabel::Method,
+            method_label(&parts.method, &config),
+        );
+        config.push_label(&mut labels, DefaultLabel::Path, path);
+
+        Ok(Self { labels })
+    }
+}
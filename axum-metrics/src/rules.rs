@@ -0,0 +1,93 @@
+use std::{
+    cell::Cell,
+    collections::{hash_map::RandomState, HashMap, HashSet},
+    fmt,
+    hash::{BuildHasher, Hasher},
+    sync::Arc,
+};
+
+use axum::http::Method;
+
+type Predicate = Arc<dyn Fn(&Method, &str) -> bool + Send + Sync>;
+
+/// Which requests a layer records, and how many of them feed its histograms.
+#[derive(Clone)]
+pub(crate) struct Rules {
+    pub(crate) include_paths: HashSet<String>,
+    pub(crate) exclude_paths: HashSet<String>,
+    pub(crate) exclude_methods: HashSet<Method>,
+    pub(crate) predicates: Vec<Predicate>,
+    pub(crate) sample_rate: f64,
+    pub(crate) route_sample_rates: HashMap<String, f64>,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            include_paths: HashSet::new(),
+            exclude_paths: HashSet::new(),
+            exclude_methods: HashSet::new(),
+            predicates: Vec::new(),
+            sample_rate: 1.0,
+            route_sample_rates: HashMap::new(),
+        }
+    }
+}
+
+impl fmt::Debug for Rules {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Rules")
+            .field("include_paths", &self.include_paths)
+            .field("exclude_paths", &self.exclude_paths)
+            .field("exclude_methods", &self.exclude_methods)
+            .field("sample_rate", &self.sample_rate)
+            .field("route_sample_rates", &self.route_sample_rates)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Rules {
+    /// Whether requests with this method to this route are recorded at all.
+    pub(crate) fn records(&self, method: &Method, route: &str) -> bool {
+        (self.include_paths.is_empty() || self.include_paths.contains(route))
+            && !self.exclude_paths.contains(route)
+            && !self.exclude_methods.contains(method)
+            && self
+                .predicates
+                .iter()
+                .all(|predicate| predicate(method, route))
+    }
+
+    /// Whether the histograms of a request to this route are recorded.
+    pub(crate) fn sampled(&self, route: &str) -> bool {
+        let rate = self
+            .route_sample_rates
+            .get(route)
+            .copied()
+            .unwrap_or(self.sample_rate);
+
+        if rate >= 1.0 {
+            true
+        } else if rate <= 0.0 {
+            false
+        } else {
+            random() < rate
+        }
+    }
+}
+
+/// Uniform float in `[0, 1)` from a per-thread xorshift generator.
+fn random() -> f64 {
+    thread_local! {
+        static STATE: Cell<u64> = Cell::new(RandomState::new().build_hasher().finish() | 1);
+    }
+
+    STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        state.set(x);
+        (x >> 11) as f64 / (1u64 << 53) as f64
+    })
+}
@@ -0,0 +1,183 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use metrics::{
+    Counter, Gauge, Histogram, HistogramFn, Key, KeyName, LocalRecorderGuard, Metadata, Recorder,
+    SharedString, Unit,
+};
+
+/// Recorder capturing metrics in memory so tests can assert on what a service emitted.
+///
+/// Label filters passed to the helpers match every series carrying at least those labels, and the
+/// values of all matching series are summed.
+///
+/// ```no_run
+/// # use axum::{body::Body, http::Request, routing::get, Router};
+/// # use axum_metrics::{MetricLayer, TestRecorder};
+/// # use tower::ServiceExt;
+/// #[tokio::test]
+/// async fn counts_requests() {
+///     let recorder = TestRecorder::new();
+///     let _guard = recorder.local();
+///
+///     let app = Router::new()
+///         .route("/users/:id", get(|| async { "user" }))
+///         .route_layer(MetricLayer::default());
+///     app.oneshot(Request::get("/users/1").body(Body::empty()).unwrap())
+///         .await
+///         .unwrap();
+///
+///     recorder.assert_counter(
+///         "http_server_requests_total",
+///         &[("path", "/users/:id"), ("status", "200")],
+///         1,
+///     );
+///     assert_eq!(
+///         recorder.histogram("http_server_requests_duration_seconds", &[]).len(),
+///         1
+///     );
+/// }
+/// # fn main() {}
+/// ```
+#[derive(Debug, Default)]
+pub struct TestRecorder {
+    counters: Mutex<HashMap<Key, Arc<AtomicU64>>>,
+    gauges: Mutex<HashMap<Key, Arc<AtomicU64>>>,
+    histograms: Mutex<HashMap<Key, Arc<Samples>>>,
+}
+
+impl TestRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets this recorder for the current thread until the guard is dropped.
+    ///
+    /// Metrics recorded on other threads are not captured, so use a current thread runtime such
+    /// as the default one of `#[tokio::test]`.
+    pub fn local(&self) -> LocalRecorderGuard<'_> {
+        metrics::set_default_local_recorder(self)
+    }
+
+    /// Sum of the counters named `name` with the given labels.
+    pub fn counter(&self, name: &str, labels: &[(&str, &str)]) -> u64 {
+        matching(&self.counters, name, labels)
+            .into_iter()
+            .map(|counter| counter.load(Ordering::Acquire))
+            .sum()
+    }
+
+    /// Sum of the gauges named `name` with the given labels.
+    pub fn gauge(&self, name: &str, labels: &[(&str, &str)]) -> f64 {
+        matching(&self.gauges, name, labels)
+            .into_iter()
+            .map(|gauge| f64::from_bits(gauge.load(Ordering::Acquire)))
+            .sum()
+    }
+
+    /// Samples recorded by the histograms named `name` with the given labels.
+    pub fn histogram(&self, name: &str, labels: &[(&str, &str)]) -> Vec<f64> {
+        matching(&self.histograms, name, labels)
+            .into_iter()
+            .flat_map(|samples| samples.0.lock().unwrap().clone())
+            .collect()
+    }
+
+    /// Panics unless the counters named `name` with the given labels add up to `expected`.
+    #[track_caller]
+    pub fn assert_counter(&self, name: &str, labels: &[(&str, &str)], expected: u64) {
+        let actual = self.counter(name, labels);
+        if actual != expected {
+            let recorded = self
+                .counters
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(key, _)| key.name() == name)
+                .map(|(key, counter)| format!("\n  {key} = {}", counter.load(Ordering::Acquire)))
+                .collect::<String>();
+            panic!(
+                "expected counter {name} with labels {labels:?} to be {expected}, got {actual}\n\
+                 recorded:{recorded}"
+            );
+        }
+    }
+
+    /// Panics unless the gauges named `name` with the given labels add up to `expected`.
+    #[track_caller]
+    pub fn assert_gauge(&self, name: &str, labels: &[(&str, &str)], expected: f64) {
+        let actual = self.gauge(name, labels);
+        assert_eq!(
+            actual, expected,
+            "expected gauge {name} with labels {labels:?} to be {expected}, got {actual}"
+        );
+    }
+
+    /// Panics unless the histograms named `name` with the given labels hold `expected` samples.
+    #[track_caller]
+    pub fn assert_histogram_count(&self, name: &str, labels: &[(&str, &str)], expected: usize) {
+        let actual = self.histogram(name, labels).len();
+        assert_eq!(
+            actual, expected,
+            "expected histogram {name} with labels {labels:?} to have {expected} samples, got \
+             {actual}"
+        );
+    }
+}
+
+fn matching<T: Clone>(
+    metrics: &Mutex<HashMap<Key, T>>,
+    name: &str,
+    labels: &[(&str, &str)],
+) -> Vec<T> {
+    metrics
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(key, _)| {
+            key.name() == name
+                && labels.iter().all(|(label_key, label_value)| {
+                    key.labels()
+                        .any(|label| label.key() == *label_key && label.value() == *label_value)
+                })
+        })
+        .map(|(_, value)| value.clone())
+        .collect()
+}
+
+#[derive(Debug, Default)]
+struct Samples(Mutex<Vec<f64>>);
+
+impl HistogramFn for Samples {
+    fn record(&self, value: f64) {
+        self.0.lock().unwrap().push(value);
+    }
+}
+
+impl Recorder for TestRecorder {
+    fn describe_counter(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+
+    fn describe_gauge(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+
+    fn describe_histogram(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+
+    fn register_counter(&self, key: &Key, _metadata: &Metadata<'_>) -> Counter {
+        let mut counters = self.counters.lock().unwrap();
+        Counter::from_arc(counters.entry(key.clone()).or_default().clone())
+    }
+
+    fn register_gauge(&self, key: &Key, _metadata: &Metadata<'_>) -> Gauge {
+        let mut gauges = self.gauges.lock().unwrap();
+        Gauge::from_arc(gauges.entry(key.clone()).or_default().clone())
+    }
+
+    fn register_histogram(&self, key: &Key, _metadata: &Metadata<'_>) -> Histogram {
+        let mut histograms = self.histograms.lock().unwrap();
+        Histogram::from_arc(histograms.entry(key.clone()).or_default().clone())
+    }
+}
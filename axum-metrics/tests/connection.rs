@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+use axum::{routing::get, Router};
+use axum_metrics::{
+    ConnectionMetrics, MetricLayer, TestRecorder, CONNECTIONS_ACCEPTED_TOTAL, CONNECTIONS_OPEN,
+    CONNECTION_REQUESTS, CONNECTION_REUSED_REQUESTS_TOTAL,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+/// Sends a keep-alive request and reads its `ok` response.
+async fn request(stream: &mut TcpStream) {
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nhost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\nok") {
+        let mut buf = [0; 1024];
+        let read = stream.read(&mut buf).await.unwrap();
+        assert_ne!(read, 0, "connection closed before the response");
+        response.extend_from_slice(&buf[..read]);
+    }
+}
+
+#[tokio::test]
+async fn records_keep_alive_connections() {
+    let recorder = TestRecorder::new();
+    let _guard = recorder.local();
+    let name = |name: &str| format!("api_{name}");
+
+    let layer = MetricLayer::builder()
+        .prefix("api")
+        .constant_label("service", "test")
+        .build();
+    let app = Router::new()
+        .route("/", get(|| async { "ok" }))
+        .layer(layer.clone());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let make_service = ConnectionMetrics::with_layer(app.into_make_service(), &layer);
+        axum::serve(listener, make_service).await.unwrap()
+    });
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    request(&mut stream).await;
+    request(&mut stream).await;
+    recorder.assert_gauge(
+        &name(CONNECTIONS_OPEN),
+        &[("service", "test"), ("protocol_version", "1.1")],
+        1.0,
+    );
+
+    // The connection only counts as closed once hyper has noticed and dropped its service.
+    drop(stream);
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while recorder.gauge(&name(CONNECTIONS_OPEN), &[]) != 0.0 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .expect("connection still open");
+
+    recorder.assert_counter(&name(CONNECTIONS_ACCEPTED_TOTAL), &[("service", "test")], 1);
+    assert_eq!(
+        recorder.histogram(&name(CONNECTION_REQUESTS), &[("protocol_version", "1.1")]),
+        [2.0]
+    );
+    recorder.assert_counter(
+        &name(CONNECTION_REUSED_REQUESTS_TOTAL),
+        &[("service", "test"), ("protocol_version", "1.1")],
+        1,
+    );
+}
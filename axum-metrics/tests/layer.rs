@@ -0,0 +1,307 @@
+use std::{
+    collections::VecDeque,
+    convert::Infallible,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use axum::{
+    body::Body,
+    http::{HeaderMap, Request, Response, StatusCode},
+    routing::{get, post},
+    Router,
+};
+use axum_metrics::{
+    Buckets, Conventions, MetricLayer, RequestBodySizeLayer, StatusGranularity, TestRecorder,
+    ERRORS_TOTAL, REQUESTS_DURATION_SECONDS, REQUESTS_IN_FLIGHT, REQUESTS_TOTAL,
+    REQUEST_SIZE_BYTES, RESPONSE_SIZE_BYTES,
+};
+use bytes::Bytes;
+use http_body::Frame;
+use http_body_util::BodyExt;
+use tower::{service_fn, timeout::TimeoutLayer, BoxError, Layer, ServiceExt};
+
+/// Body streaming its frames one by one, without a known size.
+struct Frames(VecDeque<Frame<Bytes>>);
+
+impl Frames {
+    fn new(chunks: &[&'static str], trailers: Option<HeaderMap>) -> Self {
+        let mut frames = chunks
+            .iter()
+            .map(|chunk| Frame::data(Bytes::from_static(chunk.as_bytes())))
+            .collect::<VecDeque<_>>();
+        frames.extend(trailers.map(Frame::trailers));
+        Self(frames)
+    }
+}
+
+impl http_body::Body for Frames {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        Poll::Ready(self.0.pop_front().map(Ok))
+    }
+}
+
+fn get_request(uri: &str) -> Request<Body> {
+    Request::get(uri).body(Body::empty()).unwrap()
+}
+
+#[tokio::test]
+async fn labels_requests_by_matched_path() {
+    let recorder = TestRecorder::new();
+    let _guard = recorder.local();
+
+    let app = Router::new()
+        .route("/users/:id", get(|| async { "user" }))
+        .route_layer(MetricLayer::default());
+    for uri in ["/users/1", "/users/2"] {
+        app.clone().oneshot(get_request(uri)).await.unwrap();
+    }
+
+    recorder.assert_counter(
+        REQUESTS_TOTAL,
+        &[("method", "GET"), ("path", "/users/:id"), ("status", "200")],
+        2,
+    );
+    recorder.assert_histogram_count(REQUESTS_DURATION_SECONDS, &[("path", "/users/:id")], 2);
+}
+
+#[tokio::test]
+async fn records_outcomes_without_timing_failures() {
+    let recorder = TestRecorder::new();
+    let _guard = recorder.local();
+    let layer = MetricLayer::builder().time_failures(false).build();
+
+    let app = Router::new()
+        .route("/ok", get(|| async { "ok" }))
+        .route("/slow", get(std::future::pending::<()>))
+        .route_layer(layer.clone());
+    app.clone().oneshot(get_request("/ok")).await.unwrap();
+    // Dropping the response future, e.g. on client disconnect, cancels the request.
+    let cancelled = tokio::time::timeout(
+        Duration::from_millis(10),
+        app.clone().oneshot(get_request("/slow")),
+    )
+    .await;
+    assert!(cancelled.is_err());
+
+    let service = layer.layer(
+        TimeoutLayer::new(Duration::from_millis(10)).layer(service_fn(
+            |_request: Request<Body>| async {
+                std::future::pending::<Result<Response<Body>, BoxError>>().await
+            },
+        )),
+    );
+    let result = service.oneshot(get_request("/timeout")).await;
+    assert!(result.is_err());
+
+    recorder.assert_counter(REQUESTS_TOTAL, &[("outcome", "completed")], 1);
+    recorder.assert_counter(REQUESTS_TOTAL, &[("outcome", "cancelled")], 1);
+    recorder.assert_counter(REQUESTS_TOTAL, &[("outcome", "error")], 1);
+    recorder.assert_counter(ERRORS_TOTAL, &[("error_kind", "timeout")], 1);
+    recorder.assert_histogram_count(REQUESTS_DURATION_SECONDS, &[], 1);
+    recorder.assert_histogram_count(REQUESTS_DURATION_SECONDS, &[("path", "/ok")], 1);
+}
+
+#[tokio::test]
+async fn in_flight_gauge_returns_to_zero() {
+    let recorder = TestRecorder::new();
+    let _guard = recorder.local();
+
+    let app = Router::new()
+        .route("/ok", get(|| async { "ok" }))
+        .route("/slow", get(std::future::pending::<()>))
+        .route_layer(MetricLayer::default());
+
+    let mut slow = Box::pin(app.clone().oneshot(get_request("/slow")));
+    assert!(tokio::time::timeout(Duration::from_millis(10), &mut slow)
+        .await
+        .is_err());
+    recorder.assert_gauge(REQUESTS_IN_FLIGHT, &[("path", "/slow")], 1.0);
+
+    app.oneshot(get_request("/ok")).await.unwrap();
+    recorder.assert_gauge(REQUESTS_IN_FLIGHT, &[("path", "/ok")], 0.0);
+
+    drop(slow);
+    recorder.assert_gauge(REQUESTS_IN_FLIGHT, &[], 0.0);
+}
+
+#[tokio::test]
+async fn counts_streamed_body_sizes() {
+    let recorder = TestRecorder::new();
+    let _guard = recorder.local();
+
+    let app = Router::new()
+        .route(
+            "/echo",
+            post(|body: Body| async move {
+                let received = body.collect().await.unwrap().to_bytes().len();
+                assert_eq!(received, 3);
+                Body::new(Frames::new(&["ab", "cde", "f"], None))
+            }),
+        )
+        .route_layer(RequestBodySizeLayer::new())
+        .route_layer(MetricLayer::default());
+
+    let request = Request::post("/echo")
+        .body(Body::new(Frames::new(&["a", "bc"], None)))
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    // Streamed sizes are only known once the body is over.
+    recorder.assert_histogram_count(RESPONSE_SIZE_BYTES, &[], 0);
+    response.into_body().collect().await.unwrap();
+
+    assert_eq!(recorder.histogram(REQUEST_SIZE_BYTES, &[]), [3.0]);
+    assert_eq!(
+        recorder.histogram(RESPONSE_SIZE_BYTES, &[("status", "200")]),
+        [6.0]
+    );
+}
+
+#[tokio::test]
+async fn labels_grpc_calls_by_trailer_status() {
+    let recorder = TestRecorder::new();
+    let _guard = recorder.local();
+
+    let app = Router::new()
+        .route(
+            "/helloworld.Greeter/SayHello",
+            post(|| async {
+                let mut trailers = HeaderMap::new();
+                trailers.insert("grpc-status", "5".parse().unwrap());
+                Body::new(Frames::new(&["message"], Some(trailers)))
+            }),
+        )
+        .route_layer(MetricLayer::builder().grpc(true).build());
+
+    let request = Request::post("/helloworld.Greeter/SayHello")
+        .header("content-type", "application/grpc")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    // The call is only recorded once the trailers have been streamed.
+    recorder.assert_counter(REQUESTS_TOTAL, &[], 0);
+    response.into_body().collect().await.unwrap();
+
+    recorder.assert_counter(
+        REQUESTS_TOTAL,
+        &[
+            ("grpc_service", "helloworld.Greeter"),
+            ("grpc_method", "SayHello"),
+            ("grpc_status", "5"),
+        ],
+        1,
+    );
+}
+
+#[tokio::test]
+async fn excludes_and_samples_requests() {
+    let recorder = TestRecorder::new();
+    let _guard = recorder.local();
+
+    let app = Router::new()
+        .route("/health", get(|| async { "ok" }))
+        .route("/hot", get(|| async { "hot" }))
+        .route("/cold", get(|| async { "cold" }))
+        .route_layer(
+            MetricLayer::builder()
+                .exclude_path("/health")
+                .route_sample_rate("/hot", 0.0)
+                .build(),
+        );
+    for uri in ["/health", "/hot", "/hot", "/cold"] {
+        app.clone().oneshot(get_request(uri)).await.unwrap();
+    }
+
+    recorder.assert_counter(REQUESTS_TOTAL, &[("path", "/health")], 0);
+    // Unsampled requests are still counted, but left out of the histograms.
+    recorder.assert_counter(REQUESTS_TOTAL, &[("path", "/hot")], 2);
+    recorder.assert_histogram_count(REQUESTS_DURATION_SECONDS, &[("path", "/hot")], 0);
+    recorder.assert_histogram_count(REQUESTS_DURATION_SECONDS, &[("path", "/cold")], 1);
+}
+
+#[tokio::test]
+async fn labels_url_scheme_only_when_known() {
+    let recorder = TestRecorder::new();
+    let _guard = recorder.local();
+    let duration = "http.server.request.duration";
+
+    for (prefix, builder) in [
+        ("unknown", MetricLayer::builder()),
+        ("tls", MetricLayer::builder().scheme("https")),
+    ] {
+        let app = Router::new()
+            .route("/", get(|| async { "ok" }))
+            .route_layer(
+                builder
+                    .conventions(Conventions::OpenTelemetry)
+                    .prefix(prefix)
+                    .build(),
+            );
+        app.oneshot(get_request("/")).await.unwrap();
+    }
+
+    let unknown = format!("unknown.{duration}");
+    recorder.assert_histogram_count(&unknown, &[], 1);
+    recorder.assert_histogram_count(&unknown, &[("url.scheme", "http")], 0);
+    recorder.assert_histogram_count(&format!("tls.{duration}"), &[("url.scheme", "https")], 1);
+}
+
+#[tokio::test]
+async fn labels_failures_by_error_type_under_otel() {
+    let recorder = TestRecorder::new();
+    let _guard = recorder.local();
+    let duration = "http.server.request.duration";
+    let layer = MetricLayer::builder()
+        .conventions(Conventions::OpenTelemetry)
+        .status_granularity(StatusGranularity::Both)
+        .build();
+
+    let app = Router::new()
+        .route("/ok", get(|| async { "ok" }))
+        .route("/broken", get(|| async { StatusCode::BAD_GATEWAY }))
+        .route_layer(layer.clone());
+    for uri in ["/ok", "/broken"] {
+        app.clone().oneshot(get_request(uri)).await.unwrap();
+    }
+    let service = layer.layer(
+        TimeoutLayer::new(Duration::from_millis(10)).layer(service_fn(
+            |_request: Request<Body>| async {
+                std::future::pending::<Result<Response<Body>, BoxError>>().await
+            },
+        )),
+    );
+    assert!(service.oneshot(get_request("/timeout")).await.is_err());
+
+    recorder.assert_histogram_count(duration, &[("http.route", "/ok")], 1);
+    recorder.assert_histogram_count(duration, &[("error.type", "502")], 1);
+    recorder.assert_histogram_count(duration, &[("error.type", "timeout")], 1);
+    recorder.assert_histogram_count(duration, &[("status_class", "5xx")], 0);
+    // The conventions have no request or error counters.
+    recorder.assert_counter(REQUESTS_TOTAL, &[], 0);
+    recorder.assert_counter("http.server.request.count", &[], 0);
+}
+
+#[test]
+#[should_panic(expected = "sample rate must be between 0.0 and 1.0")]
+fn rejects_invalid_sample_rates() {
+    let _ = MetricLayer::builder().sample_rate(f64::NAN);
+}
+
+#[test]
+#[should_panic(expected = "bucket bounds must be finite and strictly increasing")]
+fn rejects_unsorted_buckets() {
+    let _ = Buckets::new([1.0, 0.5]);
+}
+
+#[test]
+#[should_panic(expected = "bucket bounds must be finite and strictly increasing")]
+fn rejects_non_increasing_exponential_buckets() {
+    let _ = Buckets::exponential(0.0, 2.0, 4);
+}
@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+use axum::{body::Bytes, http::Request, routing::get, Router};
+use axum_metrics::{Buckets, Conventions, MetricLayer, OtlpError, OtlpExporter};
+use opentelemetry_proto::tonic::{
+    collector::metrics::v1::ExportMetricsServiceRequest,
+    common::v1::any_value::Value,
+    metrics::v1::{metric::Data, Metric},
+};
+use prost::Message;
+use tokio::{net::TcpListener, sync::mpsc};
+use tower::ServiceExt;
+
+/// Stand-in collector forwarding every decoded export to the returned channel.
+async fn collector() -> (String, mpsc::UnboundedReceiver<ExportMetricsServiceRequest>) {
+    let (exports, exports_rx) = mpsc::unbounded_channel();
+    let app = Router::new().route(
+        "/v1/metrics",
+        axum::routing::post(move |body: Bytes| async move {
+            let request = ExportMetricsServiceRequest::decode(body).expect("valid OTLP payload");
+            let _ = exports.send(request);
+        }),
+    );
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (format!("http://{addr}/v1/metrics"), exports_rx)
+}
+
+fn metric<'a>(export: &'a ExportMetricsServiceRequest, name: &str) -> &'a Metric {
+    export.resource_metrics[0].scope_metrics[0]
+        .metrics
+        .iter()
+        .find(|metric| metric.name == name)
+        .unwrap_or_else(|| panic!("no metric named {name}"))
+}
+
+#[tokio::test]
+async fn exports_metrics_to_collector() {
+    let (endpoint, mut exports) = collector().await;
+    let layer = MetricLayer::builder()
+        .conventions(Conventions::OpenTelemetry)
+        .route_buckets("/users/:id", Buckets::new([0.5, 1.0]))
+        .build();
+    let handle = OtlpExporter::new()
+        .endpoint(endpoint)
+        .interval(Duration::from_secs(3600))
+        .resource_attribute("service.name", "test")
+        .layer_buckets(&layer)
+        .install()
+        .unwrap();
+
+    let app = Router::new()
+        .route("/users/:id", get(|| async { "user" }))
+        .layer(layer);
+    for _ in 0..2 {
+        app.clone()
+            .oneshot(
+                Request::get("/users/1")
+                    .body(axum::body::Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+    }
+
+    // Nothing is exported before the interval elapses, so this is the shutdown flush.
+    handle.shutdown().await.unwrap();
+    let export = exports.try_recv().expect("metrics flushed on shutdown");
+
+    let resource = export.resource_metrics[0].resource.as_ref().unwrap();
+    assert_eq!(resource.attributes[0].key, "service.name");
+
+    let duration = metric(&export, "http.server.request.duration");
+    assert_eq!(duration.unit, "s");
+    let Some(Data::Histogram(histogram)) = &duration.data else {
+        panic!("duration is not a histogram");
+    };
+    let point = &histogram.data_points[0];
+    assert_eq!(point.count, 2);
+    assert_eq!(point.explicit_bounds, [0.5, 1.0]);
+    assert_eq!(point.bucket_counts, [2, 0, 0]);
+    assert!(point.attributes.iter().any(|attribute| {
+        attribute.key == "http.route"
+            && attribute
+                .value
+                .as_ref()
+                .and_then(|value| value.value.as_ref())
+                == Some(&Value::StringValue("/users/:id".to_string()))
+    }));
+
+    // Requests are only counted by the duration histogram under the conventions.
+    let names = export.resource_metrics[0].scope_metrics[0]
+        .metrics
+        .iter()
+        .map(|metric| metric.name.as_str())
+        .collect::<Vec<_>>();
+    assert!(
+        !names.iter().any(|name| name.ends_with(".count")),
+        "{names:?}"
+    );
+}
+
+#[test]
+fn install_requires_runtime() {
+    let result = OtlpExporter::new().install();
+    assert!(matches!(result, Err(OtlpError::NoRuntime)));
+}
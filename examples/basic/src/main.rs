@@ -1,18 +1,25 @@
 use std::time::Duration;
 
 use axum::{routing::get, Router};
-use axum_metrics::MetricLayer;
+use axum_metrics::{ConnectionMetrics, MetricLayer, PrometheusExporter};
 
 #[tokio::main]
 async fn main() {
+    let metrics = PrometheusExporter::new().install().unwrap();
+
+    let layer = MetricLayer::builder().time_failures(true).build();
     let app = Router::new()
         .route("/", get(root))
-        .route_layer(MetricLayer {
-            time_failures: true,
-        });
+        .route_layer(layer.clone())
+        .merge(metrics);
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(
+        listener,
+        ConnectionMetrics::with_layer(app.into_make_service(), &layer),
+    )
+    .await
+    .unwrap();
 }
 
 async fn root() -> &'static str {